@@ -0,0 +1,218 @@
+use crate::data::{self, Loader};
+use crate::entities::{Level, Player};
+use crate::utils::Cooldown;
+use crate::HEIGHT;
+use raylib::prelude::*;
+
+const ABILITY_FILE: &str = "assets/abilities.txt";
+const DEFAULT_ABILITIES: &str = include_str!("../assets/abilities.txt");
+pub const MAX_SLOTS: usize = 2;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AbilityEffect {
+    Shockwave,
+    TimeSlow,
+    Decoy
+}
+
+#[derive(Clone)]
+pub struct AbilityDef {
+    pub name: String,
+    pub effect: AbilityEffect,
+    pub cooldown: f32,
+    pub radius: f32,
+    pub duration: f32,
+    pub strength: f32,
+    pub weight: u32
+}
+
+pub struct AbilityBook {
+    pub abilities: Vec<AbilityDef>
+}
+
+impl AbilityBook {
+    pub fn load(loader: &mut Loader) -> Self {
+        loader.load(ABILITY_FILE, DEFAULT_ABILITIES, Self::parse)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut book = Self { abilities: Vec::new() };
+        for mut line in data::lines(source) {
+            if line.keyword != "ability" { return Err(line.unknown()) }
+            let name = line.name()?;
+            let fields = line.fields()?;
+            let effect = match fields.field("effect").ok_or(fields.error("ability needs an effect"))? {
+                "shockwave" => AbilityEffect::Shockwave,
+                "time_slow" => AbilityEffect::TimeSlow,
+                "decoy" => AbilityEffect::Decoy,
+                other => return Err(fields.error(format!("unknown effect `{other}`")))
+            };
+            book.abilities.push(AbilityDef {
+                name: name.to_string(),
+                effect,
+                cooldown: fields.number_field("cooldown", 10.0)?,
+                radius: fields.number_field("radius", 0.2)? * HEIGHT,
+                duration: fields.number_field("duration", 0.0)?,
+                strength: fields.number_field("strength", 1.0)?,
+                weight: fields.number_field("weight", 1.0)? as u32
+            });
+        }
+        Ok(book)
+    }
+}
+
+pub struct AbilitySlot {
+    pub ability: AbilityDef,
+    pub cooldown: Cooldown
+}
+
+impl AbilitySlot {
+    // Rarer cards shave a tenth off the cooldown per step
+    pub fn new(ability: AbilityDef, cooldown_multiplier: f32) -> Self {
+        let cooldown = ability.cooldown * 10.0 * cooldown_multiplier;
+        Self {
+            ability,
+            cooldown: Cooldown {
+                cooldown,
+                cooldown_value: 0.0
+            }
+        }
+    }
+    pub fn tick(&mut self, dt: f32) {
+        self.cooldown.cooldown_value = (self.cooldown.cooldown_value - 10.0 * dt).max(0.0);
+    }
+    // Starts the cooldown if the slot is ready, and says whether it was
+    pub fn trigger(&mut self) -> bool {
+        if self.cooldown.cooldown_value > 0.0 { return false }
+        self.cooldown.cooldown_value = self.cooldown.cooldown;
+        true
+    }
+    // 0 when ready, 1 right after use
+    pub fn charge(&self) -> f32 {
+        self.cooldown.cooldown_value / self.cooldown.cooldown
+    }
+}
+
+// Something an ability left in the world for a while
+pub struct FieldEffect {
+    pub effect: AbilityEffect,
+    pub position: Vector2,
+    pub radius: f32,
+    pub strength: f32,
+    pub remaining: Cooldown
+}
+
+impl FieldEffect {
+    // Shockwaves are instant, their field only lives long enough to draw the ring
+    pub fn new(ability: &AbilityDef, position: Vector2) -> Self {
+        let duration = if ability.effect == AbilityEffect::Shockwave { 0.3 } else { ability.duration };
+        Self {
+            effect: ability.effect,
+            position,
+            radius: ability.radius,
+            strength: ability.strength,
+            remaining: Cooldown {
+                cooldown: duration * 10.0,
+                cooldown_value: duration * 10.0
+            }
+        }
+    }
+    pub fn progress(&self) -> f32 {
+        1.0 - self.remaining.cooldown_value / self.remaining.cooldown
+    }
+}
+
+// Enemies chase the newest decoy while one is standing
+pub fn decoy(fields: &[FieldEffect]) -> Option<Vector2> {
+    fields.iter().rev().find(|field| field.effect == AbilityEffect::Decoy).map(|field| field.position)
+}
+
+// Speed multiplier at a point, the strongest overlapping time bubble wins
+pub fn time_scale_at(fields: &[FieldEffect], position: Vector2) -> f32 {
+    fields.iter()
+        .filter(|field| field.effect == AbilityEffect::TimeSlow && field.position.distance_to(position) < field.radius)
+        .map(|field| field.strength)
+        .fold(1.0, f32::min)
+}
+
+fn activate(ability: &AbilityDef, position: Vector2, level: &mut Level) {
+    match ability.effect {
+        AbilityEffect::Shockwave => {
+            for enemy in &mut level.enemies {
+                let offset = enemy.fields.position - position;
+                if offset.length() > ability.radius || offset.length_sqr() == 0.0 { continue }
+                enemy.fields.position += offset.normalized().scale_by(ability.strength * HEIGHT);
+                enemy.path.clear();
+            }
+        }
+        // A decoy drags the shared flow field over to itself right away
+        AbilityEffect::Decoy => level.flow_cooldown.cooldown_value = 0.0,
+        AbilityEffect::TimeSlow => {}
+    }
+    level.field_effects.push(FieldEffect::new(ability, position));
+}
+
+pub fn ability_handler(rl: &RaylibHandle, player: &mut Player, level: &mut Level) {
+    let keys = [KeyboardKey::KEY_ONE, KeyboardKey::KEY_TWO];
+    let buttons = [GamepadButton::GAMEPAD_BUTTON_LEFT_TRIGGER_1, GamepadButton::GAMEPAD_BUTTON_RIGHT_TRIGGER_1];
+    for (index, slot) in player.abilities.iter_mut().enumerate() {
+        slot.tick(rl.get_frame_time());
+        let pressed = rl.is_key_pressed(keys[index]) || (rl.is_gamepad_available(0) && rl.is_gamepad_button_pressed(0, buttons[index]));
+        if pressed && slot.trigger() { activate(&slot.ability, player.fields.position, level) }
+    }
+
+    let decoy_before = decoy(&level.field_effects);
+    level.field_effects.retain_mut(|field| {
+        field.remaining.cooldown_value -= 10.0 * rl.get_frame_time();
+        field.remaining.cooldown_value > 0.0
+    });
+    if decoy(&level.field_effects) != decoy_before { level.flow_cooldown.cooldown_value = 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ability(source: &str) -> AbilityDef {
+        AbilityBook::parse(source).unwrap().abilities.remove(0)
+    }
+
+    #[test]
+    fn parses_abilities_with_defaults() {
+        let stasis = ability("ability Stasis effect=time_slow cooldown=14 radius=0.5 duration=4");
+        assert_eq!((stasis.name.as_str(), stasis.effect), ("Stasis", AbilityEffect::TimeSlow));
+        assert_eq!((stasis.cooldown, stasis.radius, stasis.duration), (14.0, HEIGHT / 2.0, 4.0));
+        assert_eq!((stasis.strength, stasis.weight), (1.0, 1));
+        assert_eq!(AbilityBook::parse("ability Blink cooldown=3").err(), Some("line 1: ability needs an effect".to_string()));
+        assert_eq!(AbilityBook::parse("ability Blink effect=blink").err(), Some("line 1: unknown effect `blink`".to_string()));
+        assert_eq!(AbilityBook::parse("power Blink effect=decoy").err(), Some("line 1: unknown entry `power`".to_string()));
+    }
+
+    #[test]
+    fn slots_fire_once_per_cooldown() {
+        let mut slot = AbilitySlot::new(ability("ability Shockwave effect=shockwave cooldown=2"), 0.5);
+        assert_eq!(slot.charge(), 0.0);
+        assert!(slot.trigger());
+        assert!(!slot.trigger());
+        assert_eq!(slot.charge(), 1.0);
+        // Half the cooldown from the card's rarity, so one second
+        slot.tick(0.5);
+        assert!(!slot.trigger());
+        slot.tick(0.5);
+        assert!(slot.trigger());
+    }
+
+    #[test]
+    fn activation_leaves_its_field_behind() {
+        let origin = Vector2 { x: 0.0, y: 0.0 };
+        let shockwave = FieldEffect::new(&ability("ability Shockwave effect=shockwave cooldown=8 duration=9"), origin);
+        assert_eq!(shockwave.remaining.cooldown, 3.0);
+        let stasis = FieldEffect::new(&ability("ability Stasis effect=time_slow cooldown=14 radius=0.2 duration=4 strength=0.3"), origin);
+        assert_eq!(stasis.remaining.cooldown, 40.0);
+        let decoy_at = Vector2 { x: HEIGHT, y: 0.0 };
+        let fields = [stasis, FieldEffect::new(&ability("ability Decoy effect=decoy cooldown=16 duration=5"), decoy_at)];
+        assert_eq!(time_scale_at(&fields, Vector2 { x: HEIGHT / 10.0, y: 0.0 }), 0.3);
+        assert_eq!(time_scale_at(&fields, Vector2 { x: HEIGHT / 2.0, y: 0.0 }), 1.0);
+        assert_eq!(decoy(&fields), Some(decoy_at));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::utils::Cooldown;
+use raylib::color::Color;
+
+// Everything that changes once the player is "supposed to be dead" lives here
+pub struct Afterlife {
+    pub active: bool,
+    pub timer: Cooldown,
+    pub kills: u128,
+    pub kills_to_revive: u128,
+    pub stats: AfterlifeStats
+}
+
+// Tracked apart from the living part of the run so the results screen can show both
+#[derive(Copy, Clone, Default)]
+pub struct AfterlifeStats {
+    pub entered: u32,
+    pub revived: u32,
+    pub kills: u128,
+    pub score: u128,
+    pub time: f32
+}
+
+pub struct Palette {
+    pub background: Color,
+    pub obstacle: Color,
+    pub border: Color,
+    pub enemy: Option<Color>,
+    pub enemy_text: Color,
+    pub player_text: Color
+}
+
+impl Afterlife {
+    pub const DURATION: f32 = 200.0;
+    pub const KILLS_TO_REVIVE: u128 = 10;
+    pub const SCORE_MULTIPLIER: u128 = 3;
+    pub const RELOAD_MULTIPLIER: f32 = 0.2;
+    pub const SPAWN_MULTIPLIER: f32 = 2.0;
+    pub const REVIVE_HITPOINTS: f32 = 1.0;
+
+    pub fn new() -> Self {
+        Self {
+            active: false,
+            timer: Cooldown {
+                cooldown: Self::DURATION,
+                cooldown_value: 0.0
+            },
+            kills: 0,
+            kills_to_revive: Self::KILLS_TO_REVIVE,
+            stats: AfterlifeStats::default()
+        }
+    }
+
+    // Each return trip costs more kills than the last
+    pub fn enter(&mut self) {
+        self.active = true;
+        self.timer.cooldown_value = self.timer.cooldown;
+        self.kills = 0;
+        self.stats.entered += 1;
+        self.kills_to_revive = Self::KILLS_TO_REVIVE * self.stats.entered as u128;
+    }
+
+    // Returns true once the timer runs out, which ends the run
+    pub fn tick(&mut self, dt: f32) -> bool {
+        if !self.active { return false }
+        self.stats.time += dt;
+        self.timer.cooldown_value = (self.timer.cooldown_value - 10.0 * dt).max(0.0);
+        self.timer.cooldown_value <= 0.0
+    }
+
+    // Returns the score the kill is worth and whether it earned a revive
+    pub fn on_kill(&mut self) -> (u128, bool) {
+        if !self.active { return (1, false) }
+        self.kills += 1;
+        self.stats.kills += 1;
+        self.stats.score += Self::SCORE_MULTIPLIER;
+        if self.kills < self.kills_to_revive { return (Self::SCORE_MULTIPLIER, false) }
+        self.active = false;
+        self.stats.revived += 1;
+        (Self::SCORE_MULTIPLIER, true)
+    }
+
+    pub fn reload_multiplier(&self) -> f32 {
+        if self.active { Self::RELOAD_MULTIPLIER } else { 1.0 }
+    }
+
+    // How much faster the director's budget fills
+    pub fn spawn_multiplier(&self) -> f32 {
+        if self.active { Self::SPAWN_MULTIPLIER } else { 1.0 }
+    }
+
+    // 1 when the afterlife starts, 0 when it runs out
+    pub fn remaining(&self) -> f32 {
+        self.timer.cooldown_value / self.timer.cooldown
+    }
+
+    pub fn palette(&self) -> Palette {
+        if !self.active {
+            Palette {
+                background: Color::WHITE,
+                obstacle: Color::BLACK,
+                border: Color::BLACK,
+                enemy: None,
+                enemy_text: Color::BLACK,
+                player_text: Color::RED
+            }
+        } else {
+            Palette {
+                background: Color::RED,
+                obstacle: Color::WHITE,
+                border: Color::WHITE,
+                enemy: Some(Color::BLACK),
+                enemy_text: Color::RED,
+                player_text: Color::WHITE
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::Player;
+
+    #[test]
+    fn the_dead_cannot_heal_until_revived() {
+        let mut player = Player::new();
+        player.damage.hitpoint = 0.0;
+        player.afterlife.enter();
+        assert_eq!(player.afterlife.spawn_multiplier(), Afterlife::SPAWN_MULTIPLIER);
+        player.heal(2.0);
+        assert_eq!(player.damage.hitpoint, 0.0);
+        for _ in 0..Afterlife::KILLS_TO_REVIVE { player.afterlife.on_kill(); }
+        assert!(!player.afterlife.active);
+        assert_eq!(player.afterlife.spawn_multiplier(), 1.0);
+        player.heal(2.0);
+        assert_eq!(player.damage.hitpoint, 2.0);
+    }
+}
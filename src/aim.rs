@@ -0,0 +1,161 @@
+use crate::entities::Enemy;
+use crate::navigation::NavGrid;
+use crate::HEIGHT;
+use raylib::prelude::*;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AimMode {
+    // Arrow keys only
+    Manual,
+    // Arrow keys, bent toward whatever is closest to the crosshair
+    Assist,
+    // Locks onto a target in front and fires on its own
+    Auto
+}
+
+impl AimMode {
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Manual => Self::Assist,
+            Self::Assist => Self::Auto,
+            Self::Auto => Self::Manual
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Manual => "MANUAL",
+            Self::Assist => "ASSIST",
+            Self::Auto => "AUTO"
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TargetRule {
+    Nearest,
+    Threat
+}
+
+impl TargetRule {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Nearest => "NEAREST",
+            Self::Threat => "THREAT"
+        }
+    }
+}
+
+// An accessibility option that outlives a single run, and what it is locked onto right now
+#[derive(Copy, Clone, Debug)]
+pub struct Aim {
+    pub mode: AimMode,
+    pub rule: TargetRule,
+    pub target: Option<u64>
+}
+
+impl Aim {
+    pub const RANGE: f32 = HEIGHT / 2.0;
+    // Half angles in degrees around the current facing
+    pub const AUTO_CONE: f32 = 60.0;
+    pub const ASSIST_CONE: f32 = 15.0;
+    // Half-lives in seconds of the pull toward the target, shorter pulls harder
+    pub const ASSIST_HALF_LIFE: f32 = 0.032;
+    pub const AUTO_HALF_LIFE: f32 = 0.017;
+
+    pub fn new() -> Self {
+        Self { mode: AimMode::Manual, rule: TargetRule::Threat, target: None }
+    }
+    pub fn auto_fire(&self) -> bool {
+        self.mode == AimMode::Auto && self.target.is_some()
+    }
+
+    // The direction the aim should be pulled toward and the half-life of that pull, if anything qualifies
+    pub fn update(&mut self, facing: Vector2, position: Vector2, enemies: &[Enemy], nav: &NavGrid) -> Option<(Vector2, f32)> {
+        let cone = match self.mode {
+            AimMode::Manual => { self.target = None; return None }
+            AimMode::Assist => Self::ASSIST_CONE,
+            // With nothing to face yet the whole circle counts
+            AimMode::Auto => if facing.length_sqr() > 0.0 { Self::AUTO_CONE } else { 180.0 }
+        };
+        let candidates = enemies.iter().filter(|enemy| {
+            let offset = enemy.fields.position - position;
+            offset.length() < Self::RANGE && off_angle(facing, offset) <= cone && nav.line_of_sight(position, enemy.fields.position)
+        });
+        // Assist favours whatever is nearest the crosshair so it never fights the player's choice
+        let target = match (self.mode, self.rule) {
+            (AimMode::Assist, _) => candidates.min_by(|a, b| off_angle(facing, a.fields.position - position).total_cmp(&off_angle(facing, b.fields.position - position))),
+            (_, TargetRule::Nearest) => candidates.min_by(|a, b| a.fields.position.distance_to(position).total_cmp(&b.fields.position.distance_to(position))),
+            (_, TargetRule::Threat) => candidates.max_by(|a, b| threat(a, position, Self::RANGE).total_cmp(&threat(b, position, Self::RANGE)))
+        };
+        self.target = target.map(|enemy| enemy.id);
+        let half_life = if self.mode == AimMode::Auto { Self::AUTO_HALF_LIFE } else { Self::ASSIST_HALF_LIFE };
+        target.map(|enemy| ((enemy.fields.position - position).normalized(), half_life))
+    }
+}
+
+// Degrees between where the player faces and an offset, 0 being dead ahead
+fn off_angle(facing: Vector2, offset: Vector2) -> f32 {
+    if facing.length_sqr() == 0.0 || offset.length_sqr() == 0.0 { return 0.0 }
+    facing.normalized().dot(offset.normalized()).clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+// Close, fast approaching and tough enemies rank highest; each part is roughly 0..1 before weighting
+pub fn threat(enemy: &Enemy, position: Vector2, range: f32) -> f32 {
+    let offset = position - enemy.fields.position;
+    let distance = offset.length();
+    let proximity = (1.0 - distance / range).max(0.0);
+    let heading = if distance > 0.0 { enemy.fields.direction.normalized().dot(offset.scale_by(1.0 / distance)) } else { 1.0 };
+    let closing = (heading * enemy.fields.speed * enemy.status.speed_multiplier() / (HEIGHT / 2.0)).max(0.0);
+    let toughness = (enemy.damage.hitpoint / 10.0).min(1.0);
+    2.0 * proximity + closing + 0.5 * toughness
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    // A grunt with `hitpoint` left, heading right, toward where the tests put the player
+    fn enemy(id: u64, position: Vector2, hitpoint: f32) -> Enemy {
+        let mut enemy = Enemy::new(&0, &mut StdRng::seed_from_u64(id));
+        enemy.id = id;
+        enemy.fields.position = position;
+        enemy.fields.direction = Vector2 { x: 1.0, y: 0.0 };
+        enemy.damage.hitpoint = hitpoint;
+        enemy
+    }
+
+    #[test]
+    fn threat_ranks_close_and_tough_enemies_first() {
+        let player = Vector2 { x: HEIGHT / 2.0, y: HEIGHT / 2.0 };
+        let near = enemy(1, player - Vector2 { x: HEIGHT / 10.0, y: 0.0 }, 2.0);
+        let far = enemy(2, player - Vector2 { x: HEIGHT / 3.0, y: 0.0 }, 2.0);
+        assert!(threat(&near, player, Aim::RANGE) > threat(&far, player, Aim::RANGE));
+        let tough = enemy(3, far.fields.position, 10.0);
+        assert!(threat(&tough, player, Aim::RANGE) > threat(&far, player, Aim::RANGE));
+        // Walking away counts for nothing
+        let mut leaving = enemy(4, far.fields.position, 2.0);
+        leaving.fields.direction = Vector2 { x: -1.0, y: 0.0 };
+        assert!(threat(&leaving, player, Aim::RANGE) < threat(&far, player, Aim::RANGE));
+    }
+
+    #[test]
+    fn targets_out_of_sight_are_skipped() {
+        let cell = HEIGHT / 40.0;
+        let mut nav = NavGrid::new(40, 40, cell);
+        let player = Vector2 { x: HEIGHT / 2.0, y: HEIGHT / 2.0 };
+        let enemies = [enemy(1, player - Vector2 { x: cell * 4.0, y: 0.0 }, 2.0), enemy(2, player - Vector2 { x: cell * 4.0, y: cell * 4.0 }, 2.0)];
+        let mut aim = Aim { mode: AimMode::Auto, rule: TargetRule::Threat, target: None };
+        let facing = Vector2 { x: -1.0, y: 0.0 };
+        aim.update(facing, player, &enemies, &nav);
+        assert_eq!(aim.target, Some(1));
+        // A wall between the player and the nearer one leaves only the other
+        let wall = nav.index((18, 20));
+        nav.blocked[wall] = true;
+        aim.update(facing, player, &enemies, &nav);
+        assert_eq!(aim.target, Some(2));
+        let wall = nav.index((18, 18));
+        nav.blocked[wall] = true;
+        assert_eq!(aim.update(facing, player, &enemies, &nav), None);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::data::{self, Loader};
+use crate::draft::Draft;
+use crate::weapons::{Gun, Sword};
+
+const ANIMATION_FILE: &str = "assets/animations.txt";
+const DEFAULT_ANIMATIONS: &str = include_str!("../assets/animations.txt");
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicOut,
+    BackOut,
+    // Holds the previous value until the key is reached
+    Step
+}
+
+impl Easing {
+    // Maps 0..1 onto 0..1, overshooting only for BackOut
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::QuadIn => t * t,
+            Self::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Self::QuadInOut => if t < 0.5 { 2.0 * t * t } else { 1.0 - (-2.0 * t + 2.0).powi(2) / 2.0 },
+            Self::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Self::BackOut => {
+                const C1: f32 = 1.70158;
+                1.0 + (C1 + 1.0) * (t - 1.0).powi(3) + C1 * (t - 1.0).powi(2)
+            }
+            Self::Step => if t < 1.0 { 0.0 } else { 1.0 }
+        }
+    }
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Self::Linear),
+            "quad_in" => Some(Self::QuadIn),
+            "quad_out" => Some(Self::QuadOut),
+            "quad_in_out" => Some(Self::QuadInOut),
+            "cubic_out" => Some(Self::CubicOut),
+            "back_out" => Some(Self::BackOut),
+            "step" => Some(Self::Step),
+            _ => None
+        }
+    }
+}
+
+// `easing` shapes the segment that ends on this key
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Keyframe {
+    pub time: f32,
+    pub value: f32,
+    pub easing: Easing
+}
+
+#[derive(Clone, Debug)]
+pub struct Curve {
+    pub name: String,
+    pub keys: Vec<Keyframe>
+}
+
+impl Curve {
+    // No keys at all, so it samples as 0 and is over straight away
+    pub fn still() -> Self {
+        Self { name: String::new(), keys: Vec::new() }
+    }
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map_or(0.0, |key| key.time)
+    }
+    // Holds the first and last values outside the keyed range
+    pub fn sample(&self, time: f32) -> f32 {
+        let Some(first) = self.keys.first() else { return 0.0 };
+        if time <= first.time { return first.value }
+        for pair in self.keys.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if time < to.time {
+                let local = (time - from.time) / (to.time - from.time);
+                return from.value + (to.value - from.value) * to.easing.apply(local)
+            }
+        }
+        self.keys.last().unwrap().value
+    }
+}
+
+pub struct AnimBook {
+    pub curves: Vec<Curve>
+}
+
+impl AnimBook {
+    pub fn load(loader: &mut Loader) -> Self {
+        loader.load(ANIMATION_FILE, DEFAULT_ANIMATIONS, |source| Self::parse(source)?.complete())
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut book = Self { curves: Vec::new() };
+        for mut line in data::lines(source) {
+            if line.keyword != "curve" { return Err(line.unknown()) }
+            let name = line.name()?;
+            let fields = line.fields()?;
+            if !name.contains('.') { return Err(fields.error(format!("curve `{name}` needs an owner, as in `sword.{name}`"))) }
+            // Keys here are the times, so the pairs are read in order instead of looked up
+            let mut keys: Vec<Keyframe> = Vec::new();
+            for &(time, rest) in &fields.pairs {
+                let (value, easing) = rest.split_once('@').unwrap_or((rest, "linear"));
+                let number = |text: &str| -> Result<f32, String> { text.parse().map_err(|_| fields.error(format!("`{text}` is not a number"))) };
+                let easing = Easing::parse(easing).ok_or(fields.error(format!("unknown easing `{easing}`")))?;
+                let key = Keyframe { time: number(time)?, value: number(value)?, easing };
+                if keys.last().is_some_and(|last| last.time >= key.time) { return Err(fields.error("key times must increase")) }
+                keys.push(key);
+            }
+            if keys.is_empty() { return Err(fields.error(format!("curve `{name}` has no keys"))) }
+            book.curves.push(Curve { name: name.to_string(), keys });
+        }
+        Ok(book)
+    }
+
+    // Every owner lists the motions it plays next to the code that plays them; a file missing one is rejected
+    fn complete(self) -> Result<Self, String> {
+        let owners: [(&str, &[&str]); 3] = [(Sword::CURVE_OWNER, &Sword::CURVES), (Gun::CURVE_OWNER, &Gun::CURVES), (Draft::CURVE_OWNER, &Draft::CURVES)];
+        for (owner, motions) in owners {
+            if let Some(missing) = motions.iter().find(|motion| self.get(owner, motion).is_none()) { return Err(format!("missing curve `{owner}.{missing}`")) }
+        }
+        Ok(self)
+    }
+
+    // Curves are named `<owner>.<motion>`, e.g. `sword.slash`
+    pub fn get(&self, owner: &str, motion: &str) -> Option<&Curve> {
+        self.curves.iter().find(|curve| curve.name.split_once('.') == Some((owner, motion)))
+    }
+    // Only for the motions an owner declares, which loading guarantees
+    pub fn curve(&self, owner: &str, motion: &str) -> &Curve {
+        self.get(owner, motion).unwrap_or_else(|| panic!("animation curve `{owner}.{motion}` is not loaded"))
+    }
+}
+
+// Plays a curve from one value to another, the curve giving 0..1 of the way over its own duration
+#[derive(Clone, Debug)]
+pub struct Tween {
+    pub curve: Curve,
+    pub from: f32,
+    pub to: f32,
+    pub elapsed: f32
+}
+
+impl Tween {
+    pub fn new(curve: &Curve, from: f32, to: f32) -> Self {
+        Self { curve: curve.clone(), from, to, elapsed: 0.0 }
+    }
+    // Rests at `value` until something else is played
+    pub fn still(value: f32) -> Self {
+        Self::new(&Curve::still(), value, value)
+    }
+    pub fn tick(&mut self, dt: f32) {
+        self.elapsed += dt;
+    }
+    pub fn value(&self) -> f32 {
+        self.from + (self.to - self.from) * self.curve.sample(self.elapsed)
+    }
+    pub fn done(&self) -> bool {
+        self.elapsed >= self.curve.duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::close;
+
+    const EASINGS: [Easing; 7] = [Easing::Linear, Easing::QuadIn, Easing::QuadOut, Easing::QuadInOut, Easing::CubicOut, Easing::BackOut, Easing::Step];
+
+    fn curve(source: &str) -> Curve {
+        AnimBook::parse(source).unwrap().curves.remove(0)
+    }
+
+    #[test]
+    fn easings_start_at_0_and_end_at_1() {
+        for easing in EASINGS {
+            assert!(close(easing.apply(0.0), 0.0), "{easing:?} at 0");
+            assert!(close(easing.apply(1.0), 1.0), "{easing:?} at 1");
+            // Out of range input is clamped
+            assert!(close(easing.apply(-1.0), 0.0) && close(easing.apply(2.0), 1.0), "{easing:?} clamps");
+        }
+        assert!(Easing::BackOut.apply(0.7) > 1.0);
+        assert_eq!(Easing::Step.apply(0.99), 0.0);
+    }
+
+    #[test]
+    fn samples_hit_keys_and_hold_outside_them() {
+        let curve = curve("curve test.curve 0.5=2 1=4 2=0@step");
+        assert_eq!(curve.duration(), 2.0);
+        // Before the first key and after the last the ends are held
+        assert_eq!(curve.sample(0.0), 2.0);
+        assert_eq!(curve.sample(-1.0), 2.0);
+        assert_eq!(curve.sample(3.0), 0.0);
+        // On each key
+        assert_eq!(curve.sample(0.5), 2.0);
+        assert_eq!(curve.sample(1.0), 4.0);
+        assert_eq!(curve.sample(2.0), 0.0);
+        // Between keys the easing of the later key shapes the way
+        assert!(close(curve.sample(0.75), 3.0));
+        assert_eq!(curve.sample(1.9), 4.0);
+    }
+
+    #[test]
+    fn still_tweens_rest_and_played_ones_finish() {
+        let rest = Tween::still(75.0);
+        assert_eq!(rest.value(), 75.0);
+        assert!(rest.done());
+        let mut tween = Tween::new(&curve("curve test.curve 0=0 0.5=1"), 10.0, 20.0);
+        tween.tick(0.25);
+        assert!(close(tween.value(), 15.0) && !tween.done());
+        tween.tick(0.5);
+        assert_eq!(tween.value(), 20.0);
+        assert!(tween.done());
+    }
+
+    #[test]
+    fn curves_need_an_owner_and_every_declared_motion() {
+        assert!(AnimBook::parse("curve slash 0=0 1=1").is_err());
+        let partial = AnimBook::parse("curve sword.slash 0=0 1=1").unwrap();
+        assert!(partial.get("sword", "slash").is_some());
+        assert!(partial.get("gun", "slash").is_none());
+        assert_eq!(partial.complete().err(), Some("missing curve `sword.backslash`".to_string()));
+    }
+}
@@ -48,4 +48,20 @@ impl RectanglePro {
                 }
             })
     }
-}
\ No newline at end of file
+}
+pub fn push_out_of_rect(position: Vector2, radius: f32, rect: &Rectangle) -> Vector2 {
+    let closest = Vector2 { x: position.x.clamp(rect.x, rect.x + rect.width), y: position.y.clamp(rect.y, rect.y + rect.height) };
+    let offset = position - closest;
+    let distance = offset.length();
+    if distance >= radius { return position }
+    if distance > 0.0 { return closest + offset.scale_by(radius / distance) }
+
+    // Center is inside the rectangle, leave through the nearest side
+    let exits = [
+        (position.x - rect.x, Vector2 { x: rect.x - radius, y: position.y }),
+        (rect.x + rect.width - position.x, Vector2 { x: rect.x + rect.width + radius, y: position.y }),
+        (position.y - rect.y, Vector2 { x: position.x, y: rect.y - radius }),
+        (rect.y + rect.height - position.y, Vector2 { x: position.x, y: rect.y + rect.height + radius })
+    ];
+    exits.iter().min_by(|a, b| a.0.total_cmp(&b.0)).unwrap().1
+}
@@ -0,0 +1,132 @@
+use crate::status::StatusKind;
+use crate::utils::Cooldown;
+use rand::{Rng, rngs::StdRng};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DamageType {
+    Physical,
+    Fire,
+    Poison
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DamageSource {
+    Gun,
+    Sword,
+    Status(StatusKind),
+    Item,
+    Contact,
+    Bomb
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DamageEvent {
+    pub amount: f32,
+    pub kind: DamageType,
+    pub source: DamageSource,
+    pub crit: bool
+}
+
+impl DamageEvent {
+    pub fn new(amount: f32, kind: DamageType, source: DamageSource) -> Self {
+        Self { amount, kind, source, crit: false }
+    }
+    // Crits multiply the amount up front and punch through armor
+    pub fn roll_crit(mut self, chance: f32, multiplier: f32, rng: &mut StdRng) -> Self {
+        self.crit = rng.random::<f32>() < chance;
+        if self.crit { self.amount *= multiplier }
+        self
+    }
+}
+
+// Fractions of each damage type that are ignored, 1 being immune
+#[derive(Copy, Clone, Default)]
+pub struct Resistances {
+    pub physical: f32,
+    pub fire: f32,
+    pub poison: f32
+}
+
+impl Resistances {
+    pub fn against(&self, kind: DamageType) -> f32 {
+        match kind {
+            DamageType::Physical => self.physical,
+            DamageType::Fire => self.fire,
+            DamageType::Poison => self.poison
+        }.clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct DamageSystem {
+    pub hitpoint: f32,
+    pub damage_cooldown: Cooldown,
+    pub armor: f32,
+    pub resistances: Resistances
+}
+
+impl DamageSystem {
+    // Armor takes a flat amount off physical hits but never more than half of one; resistances then scale what is left.
+    // Bombs clear the screen, so nothing softens them
+    pub fn resolve(&self, event: &DamageEvent) -> f32 {
+        if event.source == DamageSource::Bomb { return event.amount }
+        let after_armor = match event.kind {
+            DamageType::Physical if !event.crit => (event.amount - self.armor).max(event.amount / 2.0),
+            _ => event.amount
+        };
+        (after_armor * (1.0 - self.resistances.against(event.kind))).max(0.0)
+    }
+    // Returns the damage actually dealt, never more than the hitpoints left; status ticks don't start the hit cooldown
+    pub fn take(&mut self, event: &DamageEvent) -> f32 {
+        let dealt = self.resolve(event).min(self.hitpoint.max(0.0));
+        self.hitpoint -= dealt;
+        if !matches!(event.source, DamageSource::Status(_)) { self.damage_cooldown.cooldown_value = self.damage_cooldown.cooldown }
+        dealt
+    }
+    pub fn can_be_hit(&self) -> bool {
+        self.damage_cooldown.cooldown_value <= 0.0
+    }
+    pub fn is_dead(&self) -> bool {
+        self.hitpoint <= 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn armored(armor: f32, resistances: Resistances) -> DamageSystem {
+        DamageSystem { hitpoint: 100.0, damage_cooldown: Cooldown { cooldown: 0.0, cooldown_value: 0.0 }, armor, resistances }
+    }
+
+    #[test]
+    fn armor_takes_a_flat_amount_off_physical_hits_up_to_half() {
+        let target = armored(3.0, Resistances::default());
+        assert_eq!(target.resolve(&DamageEvent::new(10.0, DamageType::Physical, DamageSource::Gun)), 7.0);
+        assert_eq!(target.resolve(&DamageEvent::new(4.0, DamageType::Physical, DamageSource::Sword)), 2.0);
+        assert_eq!(target.resolve(&DamageEvent::new(4.0, DamageType::Fire, DamageSource::Item)), 4.0);
+    }
+
+    #[test]
+    fn crits_ignore_armor() {
+        let target = armored(3.0, Resistances::default());
+        let mut event = DamageEvent::new(10.0, DamageType::Physical, DamageSource::Gun);
+        event.crit = true;
+        assert_eq!(target.resolve(&event), 10.0);
+    }
+
+    #[test]
+    fn resistances_scale_their_own_type() {
+        let target = armored(0.0, Resistances { physical: 0.5, fire: 1.0, poison: -1.0 });
+        assert_eq!(target.resolve(&DamageEvent::new(10.0, DamageType::Physical, DamageSource::Gun)), 5.0);
+        assert_eq!(target.resolve(&DamageEvent::new(10.0, DamageType::Fire, DamageSource::Status(StatusKind::Burn))), 0.0);
+        // Resistances are clamped, so a negative one is no weakness
+        assert_eq!(target.resolve(&DamageEvent::new(10.0, DamageType::Poison, DamageSource::Status(StatusKind::Poison))), 10.0);
+    }
+
+    #[test]
+    fn bombs_bypass_armor_and_resistances() {
+        let target = armored(5.0, Resistances { physical: 1.0, fire: 1.0, poison: 1.0 });
+        assert_eq!(target.resolve(&DamageEvent::new(10.0, DamageType::Physical, DamageSource::Bomb)), 10.0);
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt::Display;
+use std::str::SplitWhitespace;
+
+// Every data file is read from disk so runs can be tuned without recompiling, with the bundled copy as the fallback.
+// Files that fail to parse are noted in `errors` for the overlay to show
+pub struct Loader {
+    pub errors: Vec<String>
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self { errors: Vec::new() }
+    }
+    pub fn load<T>(&mut self, path: &str, bundled: &str, parse: fn(&str) -> Result<T, String>) -> T {
+        let source = std::fs::read_to_string(path).unwrap_or_else(|_| bundled.to_string());
+        parse(&source).unwrap_or_else(|error| {
+            self.errors.push(format!("{path}: {error}, using the bundled copy"));
+            parse(bundled).unwrap_or_else(|error| panic!("bundled {path} is invalid: {error}"))
+        })
+    }
+}
+
+// One entry of a data file, `keyword [name] key=value ...`, with `#` starting a comment
+pub struct Line<'a> {
+    pub number: usize,
+    pub keyword: &'a str,
+    words: SplitWhitespace<'a>
+}
+
+pub fn lines(source: &str) -> impl Iterator<Item = Line<'_>> {
+    source.lines().enumerate().filter_map(|(index, line)| {
+        let mut words = line.split('#').next().unwrap().split_whitespace();
+        let keyword = words.next()?;
+        Some(Line { number: index + 1, keyword, words })
+    })
+}
+
+impl<'a> Line<'a> {
+    pub fn error(&self, message: impl Display) -> String {
+        format!("line {}: {message}", self.number)
+    }
+    pub fn unknown(&self) -> String {
+        self.error(format!("unknown entry `{}`", self.keyword))
+    }
+    pub fn name(&mut self) -> Result<&'a str, String> {
+        self.words.next().ok_or(self.error(format!("{} needs a name", self.keyword)))
+    }
+    pub fn fields(self) -> Result<Fields<'a>, String> {
+        let pairs = self.words.clone()
+            .map(|word| word.split_once('=').ok_or(self.error(format!("expected key=value, got `{word}`"))))
+            .collect::<Result<_, _>>()?;
+        Ok(Fields { number: self.number, pairs })
+    }
+}
+
+pub struct Fields<'a> {
+    pub number: usize,
+    // In file order, for entries whose keys are data themselves
+    pub pairs: Vec<(&'a str, &'a str)>
+}
+
+impl<'a> Fields<'a> {
+    pub fn error(&self, message: impl Display) -> String {
+        format!("line {}: {message}", self.number)
+    }
+    pub fn field(&self, key: &str) -> Option<&'a str> {
+        self.pairs.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+    pub fn number_field(&self, key: &str, default: f32) -> Result<f32, String> {
+        self.field(key).map_or(Ok(default), |value| value.parse().map_err(|_| self.error(format!("`{key}` is not a number"))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let source = "# header\n\nenemy grunt cost=1 # trailing\n   \nwave enemy=grunt";
+        let lines: Vec<(usize, &str)> = lines(source).map(|line| (line.number, line.keyword)).collect();
+        assert_eq!(lines, vec![(3, "enemy"), (5, "wave")]);
+    }
+
+    #[test]
+    fn reads_names_and_fields() {
+        let mut line = lines("drop heart weight=3 amount=x").next().unwrap();
+        assert_eq!(line.name(), Ok("heart"));
+        let fields = line.fields().unwrap();
+        assert_eq!(fields.field("weight"), Some("3"));
+        assert_eq!(fields.number_field("weight", 1.0), Ok(3.0));
+        assert_eq!(fields.number_field("despawn", 7.0), Ok(7.0));
+        assert_eq!(fields.number_field("amount", 1.0), Err("line 1: `amount` is not a number".to_string()));
+    }
+
+    #[test]
+    fn reports_malformed_lines() {
+        let mut line = lines("\nability").next().unwrap();
+        assert_eq!(line.name(), Err("line 2: ability needs a name".to_string()));
+        let line = lines("ability dash cooldown").next().unwrap();
+        assert_eq!(line.fields().err(), Some("line 1: expected key=value, got `dash`".to_string()));
+    }
+
+    #[test]
+    fn bundled_files_parse() {
+        let mut loader = Loader::new();
+        crate::director::Director::load(&mut loader);
+        crate::loot::LootTable::load(&mut loader);
+        crate::abilities::AbilityBook::load(&mut loader);
+        crate::items::ItemBook::load(&mut loader);
+        crate::anim::AnimBook::load(&mut loader);
+        assert!(loader.errors.is_empty(), "{:?}", loader.errors);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::data::{self, Loader};
+use crate::entities::EnemyKind;
+use crate::spawning::{SpawnPattern, SpawnSlot};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::collections::VecDeque;
+
+const WAVE_FILE: &str = "assets/waves.txt";
+const DEFAULT_WAVES: &str = include_str!("../assets/waves.txt");
+
+#[derive(Clone)]
+pub struct EnemyType {
+    pub name: String,
+    pub kind: EnemyKind,
+    pub cost: f32,
+    pub speed: f32,
+    pub size: f32,
+    pub weight: u32
+}
+
+#[derive(Clone)]
+pub struct Wave {
+    pub enemy: usize,
+    pub count: u32,
+    pub pattern: SpawnPattern,
+    pub at: Option<f32>,
+    pub kills: Option<u128>,
+    pub every: f32,
+    pub fired: bool
+}
+
+pub struct SpawnOrder {
+    pub enemy: usize,
+    pub pattern: SpawnPattern,
+    pub slot: SpawnSlot
+}
+
+pub struct Director {
+    pub seed: u64,
+    pub enemy_types: Vec<EnemyType>,
+    pub waves: Vec<Wave>,
+    pub time: f32,
+    pub budget: f32,
+    pub budget_rate: f32,
+    pub budget_growth: f32,
+    pub level_growth: f32,
+    pub next_filler: Option<usize>,
+    pub scheduled: VecDeque<(f32, SpawnOrder)>
+}
+
+impl Director {
+    pub fn load(loader: &mut Loader) -> Self {
+        loader.load(WAVE_FILE, DEFAULT_WAVES, Self::parse)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut director = Self {
+            seed: rand::random(),
+            enemy_types: Vec::new(),
+            waves: Vec::new(),
+            time: 0.0,
+            budget: 0.0,
+            budget_rate: 0.5,
+            budget_growth: 0.0,
+            level_growth: 0.0,
+            next_filler: None,
+            scheduled: VecDeque::new()
+        };
+        for mut line in data::lines(source) {
+            let keyword = line.keyword;
+            let name = if keyword == "enemy" { Some(line.name()?) } else { None };
+            let fields = line.fields()?;
+            match keyword {
+                "run" => {
+                    if let Some(seed) = fields.field("seed") { director.seed = seed.parse().map_err(|_| fields.error("bad seed"))? }
+                }
+                "enemy" => {
+                    let kind = match fields.field("kind").unwrap_or("grunt") {
+                        "grunt" => EnemyKind::Grunt,
+                        "elite" => EnemyKind::Elite,
+                        "boss" => EnemyKind::Boss,
+                        other => return Err(fields.error(format!("unknown enemy kind `{other}`")))
+                    };
+                    // Filler is bought while the budget covers it, so a free enemy would never stop spawning
+                    let cost = fields.number_field("cost", 1.0)?;
+                    if cost <= 0.0 { return Err(fields.error("cost must be above zero")) }
+                    director.enemy_types.push(EnemyType {
+                        name: name.unwrap().to_string(),
+                        kind,
+                        cost,
+                        speed: fields.number_field("speed", 1.0)?,
+                        size: fields.number_field("size", 1.0)?,
+                        weight: fields.number_field("weight", 0.0)? as u32
+                    });
+                }
+                "budget" => {
+                    director.budget_rate = fields.number_field("rate", director.budget_rate)?;
+                    director.budget_growth = fields.number_field("growth", director.budget_growth)?;
+                    director.level_growth = fields.number_field("level", director.level_growth)?;
+                }
+                "wave" => {
+                    let enemy = fields.field("enemy").ok_or(fields.error("wave needs an enemy"))?;
+                    let pattern = fields.field("pattern").unwrap_or("edge");
+                    director.waves.push(Wave {
+                        enemy: director.enemy_types.iter().position(|t| t.name == enemy).ok_or(fields.error(format!("unknown enemy `{enemy}`")))?,
+                        count: fields.number_field("count", 1.0)? as u32,
+                        pattern: SpawnPattern::from_name(pattern).ok_or(fields.error(format!("unknown pattern `{pattern}`")))?,
+                        at: fields.field("at").map(|_| fields.number_field("at", 0.0)).transpose()?,
+                        kills: fields.field("kills").map(|_| fields.number_field("kills", 0.0).map(|kills| kills as u128)).transpose()?,
+                        every: fields.number_field("every", 0.3)?,
+                        fired: false
+                    });
+                }
+                _ => return Err(fields.error(format!("unknown entry `{keyword}`")))
+            }
+        }
+        if director.enemy_types.is_empty() { return Err("no enemy types".to_string()) }
+        Ok(director)
+    }
+
+    pub fn rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.seed)
+    }
+
+    // Advances the clock and returns whatever should spawn this tick; `spawn_multiplier` scales the budget rate
+    pub fn update(&mut self, dt: f32, kills: u128, player_level: u128, spawn_multiplier: f32, rng: &mut StdRng) -> Vec<SpawnOrder> {
+        self.time += dt;
+        let mut orders = Vec::new();
+
+        // Scripted waves fire once all of their triggers are met
+        for wave in self.waves.iter_mut().filter(|wave| !wave.fired) {
+            if wave.at.is_some_and(|at| self.time < at) || wave.kills.is_some_and(|needed| kills < needed) { continue }
+            wave.fired = true;
+            let anchor = rng.random();
+            for index in 0..wave.count {
+                let slot = SpawnSlot { index, count: wave.count, anchor };
+                self.scheduled.push_back((self.time + wave.every * index as f32, SpawnOrder { enemy: wave.enemy, pattern: wave.pattern, slot }));
+            }
+        }
+        self.scheduled.make_contiguous().sort_by(|a, b| a.0.total_cmp(&b.0));
+        while self.scheduled.front().is_some_and(|(at, _)| *at <= self.time) { orders.push(self.scheduled.pop_front().unwrap().1) }
+
+        // Budget points buy filler enemies, scaled by time survived and player level
+        let rate = self.budget_rate * (1.0 + self.budget_growth * self.time + self.level_growth * player_level as f32) * spawn_multiplier;
+        self.budget += rate * dt;
+        if self.next_filler.is_none() { self.next_filler = self.pick_filler(rng) }
+        while let Some(filler) = self.next_filler.filter(|&filler| self.budget >= self.enemy_types[filler].cost) {
+            self.budget -= self.enemy_types[filler].cost;
+            orders.push(SpawnOrder { enemy: filler, pattern: SpawnPattern::RandomEdge, slot: SpawnSlot { index: 0, count: 1, anchor: rng.random() } });
+            self.next_filler = self.pick_filler(rng);
+        }
+        orders
+    }
+
+    fn pick_filler(&self, rng: &mut StdRng) -> Option<usize> {
+        let total: u32 = self.enemy_types.iter().map(|t| t.weight).sum();
+        if total == 0 { return None }
+        let mut roll = rng.random_range(0..total);
+        self.enemy_types.iter().position(|t| {
+            if roll < t.weight { true } else { roll -= t.weight; false }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waves_fire_once_every_trigger_is_met() {
+        let mut director = Director::parse("enemy grunt\nwave enemy=grunt count=3 at=2 kills=5 every=1").unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!(director.update(2.5, 0, 0, 1.0, &mut rng).is_empty());
+        assert_eq!(director.update(0.5, 5, 0, 1.0, &mut rng).len(), 1);
+        // The rest of the wave follows at its own interval, and it never fires again
+        assert!(director.update(0.5, 5, 0, 1.0, &mut rng).is_empty());
+        assert_eq!(director.update(0.5, 5, 0, 1.0, &mut rng).len(), 1);
+        assert_eq!(director.update(1.0, 5, 0, 1.0, &mut rng).len(), 1);
+        assert!(director.update(10.0, 50, 0, 1.0, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn budget_buys_filler_at_its_cost() {
+        let mut director = Director::parse("enemy grunt cost=2 weight=1\nbudget rate=1").unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!(director.update(1.5, 0, 0, 1.0, &mut rng).is_empty());
+        assert_eq!(director.update(5.0, 0, 0, 1.0, &mut rng).len(), 3);
+        assert_eq!(director.budget, 0.5);
+        // The afterlife multiplier speeds the budget up
+        assert_eq!(director.update(1.0, 0, 0, 4.0, &mut rng).len(), 2);
+    }
+
+    #[test]
+    fn free_or_negative_enemies_are_rejected() {
+        assert_eq!(Director::parse("enemy grunt cost=0 weight=1").err(), Some("line 1: cost must be above zero".to_string()));
+        assert!(Director::parse("enemy grunt cost=-1").is_err());
+    }
+}
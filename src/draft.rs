@@ -0,0 +1,470 @@
+use crate::entities::{Level, Player};
+use crate::stats::{Modifier, Stat};
+use crate::status::StatusKind;
+use crate::events::GameEvent;
+use crate::anim::AnimBook;
+use crate::weapons::{Evolution, FireMode, GunBranch, Magazine, Overheat, evolve, ready_evolutions};
+use crate::abilities::{AbilityBook, AbilityDef, AbilitySlot, MAX_SLOTS};
+use crate::items::{ItemBook, ItemDef, ItemEffect, Trigger};
+use crate::{SCREEN_WIDTH, SCREEN_HEIGHT};
+use rand::{Rng, rngs::StdRng};
+use raylib::{color::Color, prelude::*};
+
+const MAX_CHOICES: usize = 4;
+const CARD_WIDTH: f32 = SCREEN_WIDTH / 6.0;
+const CARD_HEIGHT: f32 = SCREEN_HEIGHT / 2.5;
+const CARD_GAP: f32 = SCREEN_WIDTH / 40.0;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Rarity {
+    Common,
+    Rare,
+    Epic,
+    Legendary
+}
+
+impl Rarity {
+    const ALL: [Self; 4] = [Self::Common, Self::Rare, Self::Epic, Self::Legendary];
+
+    pub fn weight(&self) -> u32 {
+        match self {
+            Self::Common => 60,
+            Self::Rare => 25,
+            Self::Epic => 12,
+            Self::Legendary => 3
+        }
+    }
+    // How many times the card's effect is applied
+    pub fn power(&self) -> u32 {
+        match self {
+            Self::Common => 1,
+            Self::Rare => 2,
+            Self::Epic => 3,
+            Self::Legendary => 4
+        }
+    }
+    // Rarer ability cards come with a shorter cooldown
+    pub fn cooldown_multiplier(&self) -> f32 {
+        1.0 - 0.1 * (self.power() - 1) as f32
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Common => "COMMON",
+            Self::Rare => "RARE",
+            Self::Epic => "EPIC",
+            Self::Legendary => "LEGENDARY"
+        }
+    }
+    pub fn color(&self) -> Color {
+        match self {
+            Self::Common => Color::GRAY,
+            Self::Rare => Color::BLUE,
+            Self::Epic => Color::PURPLE,
+            Self::Legendary => Color::GOLD
+        }
+    }
+    fn roll(rng: &mut StdRng) -> Self {
+        let mut roll = rng.random_range(0..Self::ALL.iter().map(|r| r.weight()).sum::<u32>());
+        *Self::ALL.iter().find(|r| if roll < r.weight() { true } else { roll -= r.weight(); false }).unwrap()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CardKind {
+    UnlockSword,
+    UpgradeGun,
+    UpgradeSword,
+    Vitality,
+    Swiftness,
+    Reach,
+    Ricochet,
+    Caliber,
+    Multishot,
+    Choke,
+    Piercing,
+    Velocity,
+    Drum,
+    Coil,
+    Magnet,
+    Precision,
+    Incendiary,
+    Venom,
+    Frostbite,
+    Chill,
+    Foresight,
+    Fortune,
+    // Index into the ability book
+    Ability(usize),
+    // Index into the item book
+    Item(usize),
+    Evolve(Evolution)
+}
+
+#[derive(Clone)]
+pub struct Card {
+    pub kind: CardKind,
+    pub rarity: Rarity,
+    pub ability: Option<AbilityDef>,
+    pub item: Option<ItemDef>
+}
+
+impl Card {
+    pub fn title(&self) -> String {
+        if let Some(ability) = &self.ability { return ability.name.clone() }
+        if let Some(item) = &self.item { return item.name.clone() }
+        match self.kind {
+            CardKind::UnlockSword => "Sword",
+            CardKind::UpgradeGun => "Gun+",
+            CardKind::UpgradeSword => "Sword+",
+            CardKind::Vitality => "Vitality",
+            CardKind::Swiftness => "Swiftness",
+            CardKind::Reach => "Reach",
+            CardKind::Ricochet => "Ricochet",
+            CardKind::Caliber => "Caliber",
+            CardKind::Multishot => "Multishot",
+            CardKind::Choke => "Choke",
+            CardKind::Piercing => "Piercing",
+            CardKind::Velocity => "Velocity",
+            CardKind::Drum => "Drum",
+            CardKind::Coil => "Coil",
+            CardKind::Magnet => "Magnet",
+            CardKind::Precision => "Precision",
+            CardKind::Incendiary => "Incendiary",
+            CardKind::Venom => "Venom",
+            CardKind::Frostbite => "Frostbite",
+            CardKind::Chill => "Chill",
+            CardKind::Foresight => "Foresight",
+            CardKind::Fortune => "Fortune",
+            CardKind::Ability(_) => "Ability",
+            CardKind::Item(_) => "Item",
+            CardKind::Evolve(evolution) => evolution.name()
+        }.to_string()
+    }
+    pub fn description(&self) -> String {
+        let power = self.rarity.power();
+        match self.kind {
+            CardKind::UnlockSword => format!("New weapon\nat level {power}"),
+            CardKind::UpgradeGun => format!("Gun\n+{power} level(s)"),
+            CardKind::UpgradeSword => format!("Sword\n+{power} level(s)"),
+            CardKind::Vitality => format!("+{power} max hitpoint(s)"),
+            CardKind::Swiftness => format!("+{}% move speed", power * 10),
+            CardKind::Reach => format!("+{}% sword reach", power * 15),
+            CardKind::Ricochet => format!("Bullets bounce\n+{power} time(s)"),
+            CardKind::Caliber => format!("+{}% bullet size", power * 20),
+            CardKind::Multishot => format!("SCATTER\n+{power} projectile(s),\nwider fan"),
+            CardKind::Choke => format!("SCATTER\n-{}% spread", power * 15),
+            CardKind::Piercing => format!("LANCE\nBullets pierce\n+{power} enemy(s)"),
+            CardKind::Velocity => format!("LANCE\n+{}% bullet speed", power * 20),
+            CardKind::Drum => format!("Magazine of {},\nfires 2.5x faster.\nR to reload", 8 + 4 * power),
+            CardKind::Coil => "Fires 3x faster\nuntil it overheats".to_string(),
+            CardKind::Magnet => format!("+{}% pickup radius", power * 25),
+            CardKind::Precision => format!("+{}% crit chance", power * 5),
+            CardKind::Incendiary => "Bullets set\nenemies on fire".to_string(),
+            CardKind::Venom => "Bullets poison,\nstacking".to_string(),
+            CardKind::Frostbite => "Sword hits\nfreeze enemies".to_string(),
+            CardKind::Chill => "Sword hits slow,\nstacking".to_string(),
+            CardKind::Foresight => "One more card\nper level up".to_string(),
+            CardKind::Fortune => format!("+{power} reroll(s)"),
+            CardKind::Ability(_) => format!(
+                "Active ability\n{:.0}s cooldown",
+                self.ability.as_ref().map_or(0.0, |ability| ability.cooldown * self.rarity.cooldown_multiplier())
+            ),
+            CardKind::Item(_) => self.item.as_ref().map_or(String::new(), |item| item_description(item, power)),
+            CardKind::Evolve(evolution) => format!("EVOLUTION\n{}", evolution.description())
+        }
+    }
+    pub fn apply(&self, player: &mut Player, draft: &mut Draft) {
+        // Effects are either on a weapon or not, rarity has nothing to multiply
+        let (gun, sword) = match self.kind {
+            CardKind::Incendiary => (Some(StatusKind::Burn), None),
+            CardKind::Venom => (Some(StatusKind::Poison), None),
+            CardKind::Frostbite => (None, Some(StatusKind::Freeze)),
+            CardKind::Chill => (None, Some(StatusKind::Slow)),
+            _ => (None, None)
+        };
+        if let Some(effect) = gun { player.weapons[0].get_gun_mut().unwrap().effects.push(effect) }
+        if let Some(effect) = sword { player.weapons[1].get_sword_mut().unwrap().effects.push(effect) }
+        if gun.is_some() || sword.is_some() { return }
+        if let Some(ability) = &self.ability {
+            if player.abilities.len() < MAX_SLOTS { player.abilities.push(AbilitySlot::new(ability.clone(), self.rarity.cooldown_multiplier())) }
+            return
+        }
+        // Rarer item cards come with more stacks
+        if let Some(item) = &self.item {
+            player.items.add(item, self.rarity.power());
+            return
+        }
+        if let CardKind::Evolve(evolution) = self.kind {
+            evolve(player, evolution);
+            return
+        }
+        let branch = match self.kind {
+            CardKind::Multishot | CardKind::Choke => Some(GunBranch::Scatter),
+            CardKind::Piercing | CardKind::Velocity => Some(GunBranch::Lance),
+            _ => None
+        };
+        // Fire modes swap out how the gun fires once, rarity only grows the magazine
+        let gun = player.weapons[0].get_gun_mut().unwrap();
+        match self.kind {
+            CardKind::Drum => {
+                gun.mode = FireMode::Magazine(Magazine::new(8 + 4 * self.rarity.power() as u8, 1.5));
+                player.stats.push(Modifier::Mul(Stat::ReloadTime, 0.4));
+                return
+            }
+            CardKind::Coil => {
+                gun.mode = FireMode::Overheat(Overheat::new(0.08, 0.5));
+                player.stats.push(Modifier::Mul(Stat::ReloadTime, 0.33));
+                return
+            }
+            _ => {}
+        }
+        if let Some(branch) = branch { player.weapons[0].get_gun_mut().unwrap().branch.get_or_insert(branch); }
+
+        for _ in 0..self.rarity.power() {
+            match self.kind {
+                CardKind::UnlockSword | CardKind::UpgradeSword => player.weapons[1].get_sword_mut().unwrap().add_level(&mut player.stats),
+                CardKind::UpgradeGun => player.weapons[0].get_gun_mut().unwrap().add_level(&mut player.stats),
+                CardKind::Vitality => {
+                    player.stats.push(Modifier::Add(Stat::MaxHitpoint, 1.0));
+                    player.heal(1.0);
+                }
+                CardKind::Swiftness => player.stats.push(Modifier::Mul(Stat::MoveSpeed, 1.1)),
+                CardKind::Reach => player.stats.push(Modifier::Mul(Stat::SwordReach, 1.15)),
+                CardKind::Ricochet => player.stats.push(Modifier::Add(Stat::Bounces, 1.0)),
+                CardKind::Caliber => player.stats.push(Modifier::Mul(Stat::BulletSize, 1.2)),
+                CardKind::Multishot => {
+                    player.stats.push(Modifier::Add(Stat::Projectiles, 1.0));
+                    player.stats.push(Modifier::Add(Stat::Spread, 2.0));
+                }
+                CardKind::Choke => player.stats.push(Modifier::Mul(Stat::Spread, 0.85)),
+                CardKind::Piercing => player.stats.push(Modifier::Add(Stat::Pierce, 1.0)),
+                CardKind::Velocity => player.stats.push(Modifier::Mul(Stat::BulletSpeed, 1.2)),
+                CardKind::Magnet => player.stats.push(Modifier::Mul(Stat::PickupRadius, 1.25)),
+                CardKind::Precision => player.stats.push(Modifier::Add(Stat::CritChance, 0.05)),
+                CardKind::Incendiary | CardKind::Venom | CardKind::Frostbite | CardKind::Chill | CardKind::Ability(_) | CardKind::Item(_) | CardKind::Evolve(_) | CardKind::Drum | CardKind::Coil => {}
+                CardKind::Foresight => draft.choices = (draft.choices + 1).min(MAX_CHOICES),
+                CardKind::Fortune => draft.rerolls = draft.rerolls.saturating_add(1)
+            }
+        }
+    }
+}
+
+// Which cards can currently be offered, with their base weights
+fn pool(player: &Player, draft: &Draft, book: &AbilityBook, items: &ItemBook) -> Vec<(CardKind, u32)> {
+    let (gun, sword) = (player.weapons[0].get_gun().unwrap(), player.weapons[1].get_sword().unwrap());
+    let sword_unlocked = sword.level > 0;
+    let mut pool = vec![(CardKind::Vitality, 15), (CardKind::Swiftness, 10), (CardKind::Caliber, 8), (CardKind::Ricochet, 4), (CardKind::Magnet, 8), (CardKind::Precision, 6), (CardKind::Fortune, 5)];
+    if !gun.is_maxed() { pool.push((CardKind::UpgradeGun, 30)) }
+    if matches!(gun.mode, FireMode::Auto) { pool.extend([(CardKind::Drum, 4), (CardKind::Coil, 4)]) }
+    // Both branches open the path, after that only the chosen one keeps coming up
+    match gun.branch {
+        None => pool.extend([(CardKind::Multishot, 6), (CardKind::Piercing, 6)]),
+        Some(GunBranch::Scatter) => pool.extend([(CardKind::Multishot, 8), (CardKind::Choke, 5)]),
+        Some(GunBranch::Lance) => pool.extend([(CardKind::Piercing, 8), (CardKind::Velocity, 5)])
+    }
+    if !sword_unlocked { pool.push((CardKind::UnlockSword, 40)) } else if !sword.is_maxed() { pool.push((CardKind::UpgradeSword, 30)) }
+    if sword_unlocked { pool.push((CardKind::Reach, 8)) }
+    if !gun.effects.contains(&StatusKind::Burn) { pool.push((CardKind::Incendiary, 5)) }
+    if !gun.effects.contains(&StatusKind::Poison) { pool.push((CardKind::Venom, 5)) }
+    if sword_unlocked && !sword.effects.contains(&StatusKind::Freeze) { pool.push((CardKind::Frostbite, 4)) }
+    if sword_unlocked && !sword.effects.contains(&StatusKind::Slow) { pool.push((CardKind::Chill, 5)) }
+    if draft.choices < MAX_CHOICES { pool.push((CardKind::Foresight, 4)) }
+    if player.abilities.len() < MAX_SLOTS {
+        for (index, ability) in book.abilities.iter().enumerate() {
+            if !player.abilities.iter().any(|slot| slot.ability.name == ability.name) { pool.push((CardKind::Ability(index), ability.weight)) }
+        }
+    }
+    for (index, item) in items.items.iter().enumerate() { pool.push((CardKind::Item(index), item.weight)) }
+    pool
+}
+
+fn item_description(item: &ItemDef, stacks: u32) -> String {
+    let when = match item.trigger {
+        Trigger::Kill => "On kill",
+        Trigger::Hit => "On hit",
+        Trigger::Hurt => "When hurt",
+        Trigger::Shot => "On shot"
+    };
+    let what = match item.effect {
+        ItemEffect::Heal => "heal",
+        ItemEffect::Chain => "chain",
+        ItemEffect::Nova => "nova",
+        ItemEffect::Pierce => "pierce"
+    };
+    let odds = if item.every > 1 { format!("every {}", item.every) } else { format!("{:.0}%", item.chance * 100.0) };
+    format!("{when}, {odds}:\n{what} {}\n{stacks} stack(s)", item.amount)
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DraftAction {
+    Move(i32),
+    Pick(usize),
+    Reroll,
+    Skip
+}
+
+pub struct Draft {
+    pub open: bool,
+    pub cards: Vec<Card>,
+    pub selected: usize,
+    // Seconds the current card has been selected, drives its lift
+    pub selected_for: f32,
+    pub choices: usize,
+    pub rerolls: u8,
+    pub queued: u32
+}
+
+impl Draft {
+    // The selected card rises along this curve
+    pub const CURVE_OWNER: &str = "card";
+    pub const CURVES: [&str; 1] = ["lift"];
+
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            cards: Vec::new(),
+            selected: 0,
+            selected_for: 0.0,
+            choices: 3,
+            rerolls: 2,
+            queued: 0
+        }
+    }
+
+    pub fn offer(&mut self, player: &Player, book: &AbilityBook, items: &ItemBook, rng: &mut StdRng) {
+        self.open = true;
+        self.selected = 0;
+        self.selected_for = 0.0;
+        self.deal(player, book, items, rng);
+    }
+
+    // Ready evolutions are always offered, the rest are distinct kinds drawn by weight, each with its own rarity roll
+    fn deal(&mut self, player: &Player, book: &AbilityBook, items: &ItemBook, rng: &mut StdRng) {
+        // Zero weights are how the data files switch a card off, so they never reach the draw
+        let mut pool: Vec<(CardKind, u32)> = pool(player, self, book, items).into_iter().filter(|(_, weight)| *weight > 0).collect();
+        self.cards.clear();
+        for evolution in ready_evolutions(player).into_iter().take(self.choices) {
+            self.cards.push(Card { kind: CardKind::Evolve(evolution), rarity: Rarity::Legendary, ability: None, item: None });
+        }
+        while self.cards.len() < self.choices && !pool.is_empty() {
+            let total: u32 = pool.iter().map(|(_, weight)| weight).sum();
+            if total == 0 { break }
+            let mut roll = rng.random_range(0..total);
+            let index = pool.iter().position(|(_, weight)| if roll < *weight { true } else { roll -= weight; false }).unwrap();
+            let (kind, _) = pool.swap_remove(index);
+            let ability = match kind {
+                CardKind::Ability(index) => Some(book.abilities[index].clone()),
+                _ => None
+            };
+            let item = match kind {
+                CardKind::Item(index) => Some(items.items[index].clone()),
+                _ => None
+            };
+            self.cards.push(Card { kind, rarity: Rarity::roll(rng), ability, item });
+        }
+    }
+
+    pub fn act(&mut self, action: DraftAction, player: &mut Player, book: &AbilityBook, items: &ItemBook, rng: &mut StdRng) {
+        match action {
+            DraftAction::Move(step) => self.selected = (self.selected as i32 + step).rem_euclid(self.cards.len().max(1) as i32) as usize,
+            DraftAction::Pick(index) => {
+                let Some(card) = self.cards.get(index).cloned() else { return };
+                card.apply(player, self);
+                // Every level up raises max HP by one on top of the card
+                player.stats.push(Modifier::Add(Stat::MaxHitpoint, 1.0));
+                player.heal(1.0);
+                self.close(player);
+            }
+            DraftAction::Reroll => if self.rerolls > 0 {
+                self.rerolls -= 1;
+                self.deal(player, book, items, rng);
+            },
+            // Skipping banks a reroll for later instead
+            DraftAction::Skip => {
+                self.rerolls = self.rerolls.saturating_add(1);
+                self.close(player);
+            }
+        }
+    }
+
+    fn close(&mut self, player: &mut Player) {
+        player.level += 1;
+        self.open = false;
+        self.queued = self.queued.saturating_sub(1);
+        self.cards.clear();
+    }
+}
+
+// Screen space rectangle of a card, shared by mouse picking and drawing
+pub fn card_rect(index: usize, count: usize) -> Rectangle {
+    let total = count as f32 * CARD_WIDTH + (count as f32 - 1.0) * CARD_GAP;
+    Rectangle {
+        x: (SCREEN_WIDTH - total) / 2.0 + index as f32 * (CARD_WIDTH + CARD_GAP),
+        y: (SCREEN_HEIGHT - CARD_HEIGHT) / 2.0,
+        width: CARD_WIDTH,
+        height: CARD_HEIGHT
+    }
+}
+
+fn read_action(rl: &RaylibHandle, draft: &mut Draft) -> Option<DraftAction> {
+    let mouse = rl.get_mouse_position();
+    if let Some(index) = (0..draft.cards.len()).find(|&index| card_rect(index, draft.cards.len()).check_collision_point_rec(mouse)) {
+        if rl.get_mouse_delta().length_sqr() > 0.0 { draft.selected = index }
+        if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) { return Some(DraftAction::Pick(index)) }
+    }
+    let digits = [KeyboardKey::KEY_ONE, KeyboardKey::KEY_TWO, KeyboardKey::KEY_THREE, KeyboardKey::KEY_FOUR];
+    if let Some(index) = digits.iter().take(draft.cards.len()).position(|&key| rl.is_key_pressed(key)) { return Some(DraftAction::Pick(index)) }
+
+    let pad = |button| rl.is_gamepad_available(0) && rl.is_gamepad_button_pressed(0, button);
+    if rl.is_key_pressed(KeyboardKey::KEY_LEFT) || rl.is_key_pressed(KeyboardKey::KEY_A) || pad(GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_LEFT) { Some(DraftAction::Move(-1)) }
+    else if rl.is_key_pressed(KeyboardKey::KEY_RIGHT) || rl.is_key_pressed(KeyboardKey::KEY_D) || pad(GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_RIGHT) { Some(DraftAction::Move(1)) }
+    else if rl.is_key_pressed(KeyboardKey::KEY_ENTER) || rl.is_key_pressed(KeyboardKey::KEY_SPACE) || pad(GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_DOWN) { Some(DraftAction::Pick(draft.selected)) }
+    else if rl.is_key_pressed(KeyboardKey::KEY_R) || pad(GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_UP) { Some(DraftAction::Reroll) }
+    else if rl.is_key_pressed(KeyboardKey::KEY_X) || pad(GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_RIGHT) { Some(DraftAction::Skip) }
+    else { None }
+}
+
+pub fn draft_handler(rl: &RaylibHandle, level: &mut Level, player: &mut Player) {
+    let selected = level.draft.selected;
+    if let Some(action) = read_action(rl, &mut level.draft) {
+        level.draft.act(action, player, &level.ability_book, &level.item_book, &mut level.rng);
+        if !level.draft.open { level.events.emit(GameEvent::LevelUp { level: player.level }) }
+    }
+    if level.draft.selected != selected { level.draft.selected_for = 0.0 } else { level.draft.selected_for += rl.get_frame_time() }
+}
+
+pub fn draw_draft(d: &mut RaylibDrawHandle, draft: &Draft, animations: &AnimBook) {
+    for (index, card) in draft.cards.iter().enumerate() {
+        let rect = card_rect(index, draft.cards.len());
+        let lift = if index == draft.selected { SCREEN_HEIGHT / 40.0 * animations.curve(Draft::CURVE_OWNER, "lift").sample(draft.selected_for) } else { 0.0 };
+        let rect = Rectangle { y: rect.y - lift, ..rect };
+        d.draw_rectangle_rec(rect, Color::RAYWHITE);
+        d.draw_rectangle_lines_ex(rect, if index == draft.selected { 8.0 } else { 4.0 }, card.rarity.color());
+        let (x, y) = (rect.x as i32 + 20, rect.y as i32 + 20);
+        d.draw_text(&format!("{}  {}", index + 1, card.rarity.name()), x, y, 20, card.rarity.color());
+        d.draw_text(&card.title(), x, y + 50, 36, Color::BLACK);
+        d.draw_text(&card.description(), x, y + 110, 22, Color::DARKGRAY);
+    }
+    let hint = format!("ENTER pick, R reroll ({} left), X skip", draft.rerolls);
+    let width = d.measure_text(&hint, 24);
+    d.draw_text(&hint, (SCREEN_WIDTH as i32 - width) / 2, (SCREEN_HEIGHT * 0.8) as i32, 24, Color::WHITE);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn zero_weight_cards_are_never_dealt() {
+        let (player, mut draft) = (Player::new(), Draft::new());
+        let book = AbilityBook::parse("ability Off effect=decoy weight=0").unwrap();
+        let items = ItemBook::parse("item Off trigger=kill effect=heal weight=0").unwrap();
+        let mut rng = StdRng::seed_from_u64(34);
+        for _ in 0..200 {
+            draft.offer(&player, &book, &items, &mut rng);
+            assert_eq!(draft.cards.len(), draft.choices);
+            assert!(draft.cards.iter().all(|card| !matches!(card.kind, CardKind::Ability(_) | CardKind::Item(_))));
+        }
+    }
+}
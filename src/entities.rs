@@ -1,16 +1,97 @@
-use crate::basic::{BallEnt};
-use crate::weapons::{Gun, Sword, Weapon};
-use crate::utils::{Cooldown, DamageSystem, Prompt, round_to_nearest};
-use rand::rng;
+use crate::basic::{BallEnt, push_out_of_rect};
+use crate::weapons::{Gun, Sword, Weapon, weapon_handler};
+use crate::utils::{BufferedInput, Cooldown};
+use crate::damage::{DamageEvent, DamageSource, DamageSystem, DamageType, Resistances};
+use crate::draft::Draft;
+use crate::navigation::{NavGrid, FlowField, PathService, CELL_SIZE};
+use crate::director::{Director, EnemyType};
+use crate::afterlife::{Afterlife, AfterlifeStats};
+use crate::stats::{Stat, Stats};
+use crate::pickups::{Pickup, pickup_handler};
+use crate::loot::LootTable;
+use crate::data::Loader;
+use crate::status::StatusEffects;
+use crate::events::{EventBus, GameEvent};
+use crate::anim::AnimBook;
+use crate::aim::Aim;
+use crate::items::{Inventory, ItemBook, item_handler};
+use crate::abilities::{AbilityBook, AbilitySlot, FieldEffect, ability_handler, decoy, time_scale_at};
+use rand::rngs::StdRng;
 use raylib::{color::Color, prelude::*};
-use rand::{Rng, rngs::ThreadRng};
-use std::{ops::Add};
+use rand::{Rng, SeedableRng};
+use std::{ops::Add, collections::VecDeque};
 use crate::{WIDTH, HEIGHT};
 
 pub struct Level {
     pub enemies: Vec<Enemy>,
-    pub enemy_cooldown: Cooldown,
-    pub prompt: Prompt
+    pub pickups: Vec<Pickup>,
+    pub loot: LootTable,
+    pub ability_book: AbilityBook,
+    pub item_book: ItemBook,
+    pub animations: AnimBook,
+    pub field_effects: Vec<FieldEffect>,
+    pub projectiles: Vec<Projectile>,
+    pub events: EventBus,
+    pub pending_spawns: VecDeque<PendingSpawn>,
+    pub director: Director,
+    pub rng: StdRng,
+    pub draft: Draft,
+    pub obstacles: Vec<Rectangle>,
+    pub nav: NavGrid,
+    pub flow_field: FlowField,
+    pub flow_cooldown: Cooldown,
+    pub paths: PathService,
+    pub next_enemy_id: u64,
+    pub spawned: u128,
+    // Data files that failed to parse and fell back to the bundled copy
+    pub load_errors: Vec<String>
+}
+
+impl Level {
+    pub fn new() -> Self {
+        let obstacles = vec![
+            Rectangle { x: WIDTH/5.0, y: HEIGHT/5.0, width: HEIGHT/10.0, height: HEIGHT/5.0 },
+            Rectangle { x: WIDTH*4.0/5.0 - HEIGHT/10.0, y: HEIGHT/5.0, width: HEIGHT/10.0, height: HEIGHT/5.0 },
+            Rectangle { x: WIDTH/5.0, y: HEIGHT*3.0/5.0, width: HEIGHT/10.0, height: HEIGHT/5.0 },
+            Rectangle { x: WIDTH*4.0/5.0 - HEIGHT/10.0, y: HEIGHT*3.0/5.0, width: HEIGHT/10.0, height: HEIGHT/5.0 },
+            Rectangle { x: WIDTH/2.0 - HEIGHT/6.0, y: HEIGHT/8.0, width: HEIGHT/3.0, height: HEIGHT/20.0 },
+            Rectangle { x: WIDTH/2.0 - HEIGHT/6.0, y: HEIGHT*7.0/8.0 - HEIGHT/20.0, width: HEIGHT/3.0, height: HEIGHT/20.0 }
+        ];
+        let mut loader = Loader::new();
+        let director = Director::load(&mut loader);
+        Self {
+            enemies: Vec::new(),
+            pickups: Vec::new(),
+            loot: LootTable::load(&mut loader),
+            ability_book: AbilityBook::load(&mut loader),
+            item_book: ItemBook::load(&mut loader),
+            animations: AnimBook::load(&mut loader),
+            field_effects: Vec::new(),
+            projectiles: Vec::new(),
+            events: EventBus::new(),
+            pending_spawns: VecDeque::new(),
+            rng: director.rng(),
+            director,
+            draft: Draft::new(),
+            nav: NavGrid::from_obstacles(&obstacles, HEIGHT/40.0),
+            obstacles,
+            flow_field: FlowField::new(),
+            flow_cooldown: Cooldown {
+                cooldown: 2.5,
+                cooldown_value: 0.0
+            },
+            paths: PathService::new(4),
+            next_enemy_id: 1,
+            spawned: 0,
+            load_errors: loader.errors
+        }
+    }
+    pub fn spawn(&mut self, mut enemy: Enemy) {
+        enemy.id = self.next_enemy_id;
+        self.next_enemy_id += 1;
+        self.spawned += 1;
+        self.enemies.push(enemy);
+    }
 }
 
 pub struct Player { 
@@ -19,17 +100,30 @@ pub struct Player {
     pub weapons: Vec<Weapon>,
     pub equipped: usize,
     pub kill_count: u128,
+    pub experience: u32,
+    pub shield: bool,
+    pub frenzy: Cooldown,
+    pub dash: Dash,
+    pub dash_input: BufferedInput,
+    pub swing_input: BufferedInput,
+    pub abilities: Vec<AbilitySlot>,
+    pub items: Inventory,
+    pub aim: Aim,
     pub damage: DamageSystem,
-    pub hit_by: Enemy
+    pub hit_by: Enemy,
+    pub score: u128,
+    pub afterlife: Afterlife,
+    pub stats: Stats
 }
 
 impl Player {
     pub fn new() -> Self {
+        let stats = Stats::new();
         Self {
             fields: BallEnt {
                 position: Vector2::new(WIDTH/2.0, HEIGHT/2.0),
                 direction: Vector2::new(0.0, 0.0),
-                speed: HEIGHT/(720.0/200.0),
+                speed: stats.get(Stat::MoveSpeed),
                 radius: HEIGHT/30.0,
                 color: Color::WHITE
             },
@@ -37,128 +131,549 @@ impl Player {
             weapons: vec![Weapon::GunW(Gun::new()), Weapon::SwordW(Sword::new())],
             equipped: 0,
             kill_count: 0,
+            experience: 0,
+            shield: false,
+            frenzy: Cooldown {
+                cooldown: 0.0,
+                cooldown_value: 0.0
+            },
+            dash: Dash::new(),
+            dash_input: BufferedInput::new(1.5),
+            swing_input: BufferedInput::new(1.5),
+            abilities: Vec::new(),
+            items: Inventory::new(),
+            aim: Aim::new(),
             damage: DamageSystem { 
-                hitpoint: 5,
+                hitpoint: stats.get(Stat::MaxHitpoint),
                 damage_cooldown: Cooldown {
                     cooldown: 30.0,
                     cooldown_value: 0.0
-                } },
-            hit_by: Enemy::new(&0)
+                },
+                armor: 0.0,
+                resistances: Resistances::default() },
+            // Only a stand-in until the first hit, so any fixed seed will do
+            hit_by: Enemy::new(&0, &mut StdRng::seed_from_u64(0)),
+            score: 0,
+            afterlife: Afterlife::new(),
+            stats
         }
     }
+    // Thrown back by a hit; movement input is ignored meanwhile
+    pub fn in_knockback(&self) -> bool {
+        self.damage.damage_cooldown.cooldown_value > 20.0
+    }
+    pub fn invincible(&self) -> bool {
+        self.dash.active.cooldown_value > 0.0
+    }
+    pub fn reload_multiplier(&self) -> f32 {
+        let frenzy = if self.frenzy.cooldown_value > 0.0 { 0.5 } else { 1.0 };
+        self.afterlife.reload_multiplier() * frenzy
+    }
+    // Nothing heals the dead; only enough kills bring them back
+    pub fn heal(&mut self, amount: f32) {
+        if self.afterlife.active { return }
+        self.damage.hitpoint = (self.damage.hitpoint + amount).min(self.stats.get(Stat::MaxHitpoint));
+    }
     
     pub fn supposed_to_be_dead(&self) -> bool {
-        self.damage.hitpoint == 0
+        self.afterlife.active
+    }
+    fn outcome(&self, cause: DeathCause, time_survived: f32) -> RunOutcome {
+        RunOutcome {
+            cause,
+            hit_by: self.hit_by.clone(),
+            time_survived,
+            kills: self.kill_count,
+            level: self.level,
+            score: self.score,
+            afterlife: self.afterlife.stats
+        }
+    }
+}
+
+pub struct Afterimage {
+    pub position: Vector2,
+    pub fade: Cooldown
+}
+
+pub struct Dash {
+    pub cooldown: Cooldown,
+    pub active: Cooldown,
+    pub direction: Vector2,
+    pub speed_multiplier: f32,
+    pub trail: Vec<Afterimage>
+}
+
+impl Dash {
+    pub fn new() -> Self {
+        Self {
+            cooldown: Cooldown {
+                cooldown: 12.0,
+                cooldown_value: 0.0
+            },
+            // Doubles as the i-frame window
+            active: Cooldown {
+                cooldown: 1.5,
+                cooldown_value: 0.0
+            },
+            direction: Vector2::new(0.0, 0.0),
+            speed_multiplier: 4.0,
+            trail: Vec::new()
+        }
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DeathCause {
+    // Touched by an enemy while already supposed to be dead
+    Contact,
+    // Same, but by something an enemy fired
+    Projectile,
+    AfterlifeExpired
+}
+
+// How a run ended, handed back to the app layer to present
+#[derive(Clone)]
+pub struct RunOutcome {
+    pub cause: DeathCause,
+    pub hit_by: Enemy,
+    pub time_survived: f32,
+    pub kills: u128,
+    pub level: u128,
+    pub score: u128,
+    pub afterlife: AfterlifeStats
+}
+
+pub struct Step {
+    pub shake: i32,
+    pub outcome: Option<RunOutcome>,
+    pub events: Vec<GameEvent>
+}
+
+// An enemy waiting out its warning marker before it enters the level
+pub struct PendingSpawn {
+    pub enemy: Enemy,
+    pub telegraph: Cooldown,
+    pub delays: u8
+}
+
+impl PendingSpawn {
+    const MAX_DELAYS: u8 = 3;
+
+    pub fn new(enemy: Enemy) -> Self {
+        Self {
+            enemy,
+            telegraph: Cooldown {
+                cooldown: 10.0,
+                cooldown_value: 10.0
+            },
+            delays: 0
+        }
+    }
+    // 0 when the marker appears, 1 when the enemy is due
+    pub fn progress(&self) -> f32 {
+        1.0 - self.telegraph.cooldown_value / self.telegraph.cooldown
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum EnemyKind {
+    Grunt,
+    Elite,
+    Boss
+}
+
 #[derive(Clone)]
 pub struct Enemy { 
     pub fields: BallEnt, 
-    pub damage: DamageSystem
+    pub damage: DamageSystem,
+    pub id: u64,
+    pub kind: EnemyKind,
+    pub max_hitpoint: f32,
+    pub experience: u32,
+    pub status: StatusEffects,
+    pub path: Vec<Vector2>,
+    pub repath: Cooldown,
+    pub retreat: Cooldown,
+    // Elites and bosses fire at whatever they are chasing
+    pub shot: Option<Cooldown>,
+    // Set by a direct hit and cleared once the enemy's own update has seen it
+    pub hit_this_tick: bool
+}
+
+// Fired by an enemy; a parry turns it around and it hurts enemies instead
+pub struct Projectile {
+    pub fields: BallEnt,
+    pub shooter: Enemy,
+    pub reflected: bool,
+    pub spent: bool
+}
+
+impl Projectile {
+    pub fn new(shooter: &Enemy, target: Vector2) -> Self {
+        Self {
+            fields: BallEnt {
+                position: shooter.fields.position,
+                direction: (target - shooter.fields.position).normalized(),
+                speed: HEIGHT/3.0,
+                radius: HEIGHT/120.0,
+                color: Color::ORANGE
+            },
+            shooter: shooter.clone(),
+            reflected: false,
+            spent: false
+        }
+    }
 }
 
 impl Enemy {
-    pub fn new(enemies_killed: &u128) -> Self {
+    pub fn from_type(enemies_killed: &u128, enemy_type: &EnemyType, rng: &mut StdRng) -> Self {
+        let mut enemy = Self::new_kind(enemies_killed, enemy_type.kind, rng);
+        enemy.fields.speed *= enemy_type.speed;
+        enemy.fields.radius *= enemy_type.size;
+        enemy.reset_hitpoint();
+        enemy
+    }
+    pub fn new_kind(enemies_killed: &u128, kind: EnemyKind, rng: &mut StdRng) -> Self {
+        let mut enemy = Self::new(enemies_killed, rng);
+        enemy.kind = kind;
+        match kind {
+            EnemyKind::Grunt => {}
+            EnemyKind::Elite => {
+                enemy.damage.armor = 0.5;
+                enemy.fields.radius *= 1.5;
+                enemy.fields.speed *= 0.9;
+                enemy.fields.color = Color::MAROON;
+                enemy.shot = Some(Cooldown { cooldown: 30.0, cooldown_value: 30.0 });
+            }
+            EnemyKind::Boss => {
+                enemy.damage.armor = 1.0;
+                enemy.damage.resistances = Resistances { physical: 0.0, fire: 0.5, poison: 0.5 };
+                enemy.fields.radius *= 2.5;
+                enemy.fields.speed *= 0.7;
+                enemy.fields.color = Color::DARKPURPLE;
+                enemy.shot = Some(Cooldown { cooldown: 20.0, cooldown_value: 20.0 });
+            }
+        }
+        enemy.reset_hitpoint();
+        enemy
+    }
+    pub fn new(enemies_killed: &u128, rng: &mut StdRng) -> Self {
         const INTERVAL: f32 = HEIGHT/180.0;
         const MIN_SIZE: f32 = HEIGHT/40.0;
         const ENEMIES_KILLED_PER_INCREASE: f32 = 20.0;
         let intervals_to_max: f32 = 1.0 + (*enemies_killed as f32 / ENEMIES_KILLED_PER_INCREASE);
-        let mut rng: ThreadRng = rng();
         let mut enemy = Self {
             fields: BallEnt {
-                position: Vector2::new(
-                    round_to_nearest(rng.random::<f32>()*(WIDTH+400.0)-200.0, 0.0, WIDTH), 
-                    round_to_nearest(rng.random::<f32>()*(HEIGHT-400.0)+200.0, HEIGHT, 0.0)
-                ),
+                position: Vector2::new(0.0, 0.0),
                 direction: Vector2::new(0.0, 0.0),
                 speed: HEIGHT/(720.0/125.0),
                 radius: rng.random_range(MIN_SIZE..=MIN_SIZE + (INTERVAL * intervals_to_max) as f32),
                 color: Color::RED
             },
             damage: DamageSystem { 
-                hitpoint: 0, 
+                hitpoint: 0.0, 
                 damage_cooldown: Cooldown { 
                     cooldown: 2.0, 
                     cooldown_value: 0.0 
-                }
-            }
+                },
+                armor: 0.0,
+                resistances: Resistances::default()
+            },
+            id: 0,
+            kind: EnemyKind::Grunt,
+            max_hitpoint: 0.0,
+            experience: 0,
+            status: StatusEffects::new(),
+            path: Vec::new(),
+            repath: Cooldown {
+                cooldown: 10.0,
+                cooldown_value: 0.0
+            },
+            retreat: Cooldown {
+                cooldown: 30.0,
+                cooldown_value: 0.0
+            },
+            shot: None,
+            hit_this_tick: false
         };
-        enemy.damage.hitpoint = ((enemy.fields.radius - MIN_SIZE) / INTERVAL as f32 + 0.001).ceil() as u8;
+        enemy.reset_hitpoint();
         enemy
     }
+    // Hitpoints follow from size, one per interval above the minimum radius, and damage shrinks it back by the same ratio
+    pub fn reset_hitpoint(&mut self) {
+        self.damage.hitpoint = ((self.fields.radius - HEIGHT/40.0) / (HEIGHT/180.0) + 0.001).ceil().max(1.0);
+        self.max_hitpoint = self.damage.hitpoint;
+        // Gems are worth the enemy's size at spawn, by area
+        self.experience = (self.fields.radius / (HEIGHT/40.0)).powi(2).round().max(1.0) as u32;
+    }
+    // Damage shrinks the enemy back down at the same ratio its hitpoints were sized from
+    pub fn hurt(&mut self, event: &DamageEvent, events: &mut EventBus) {
+        let dealt = self.damage.take(event);
+        self.fields.radius -= HEIGHT/180.0 * dealt;
+        if dealt > 0.0 {
+            if !matches!(event.source, DamageSource::Status(_)) { self.hit_this_tick = true }
+            events.emit(GameEvent::EnemyDamaged { id: self.id, position: self.fields.position, amount: dealt, crit: event.crit, source: event.source });
+        }
+    }
+    pub fn is_smart(&self) -> bool {
+        self.kind != EnemyKind::Grunt
+    }
+}
+
+// Advances one frame of gameplay; never touches the window, the caller decides what to do with the result
+pub fn simulate(rl: &RaylibHandle, player: &mut Player, input: &Vector2, level: &mut Level) -> Step {
+    let mut step = player_handler(rl, player, input, level);
+    if step.outcome.is_none() {
+        ability_handler(rl, player, level);
+        weapon_handler(rl, player, level);
+        enemy_handler(rl, level, player);
+        projectile_handler(rl, level, player);
+        pickup_handler(rl, level, player);
+    }
+    step.events = level.events.drain();
+    for event in &step.events {
+        progression_handler(level, player, event);
+        item_handler(level, player, event);
+    }
+    step
+}
+
+// Everything a kill is worth, driven by the event rather than by the combat code
+fn progression_handler(level: &mut Level, player: &mut Player, event: &GameEvent) {
+    if let GameEvent::EnemyKilled { kind, position, experience, .. } = *event {
+        player.kill_count += 1;
+        level.pickups.push(Pickup::experience(position, experience));
+        if let Some(drop) = level.loot.roll(kind, &mut level.rng) { level.pickups.push(Pickup::loot(position, drop)) }
+        let (score, revived) = player.afterlife.on_kill();
+        player.score += score;
+        if revived { player.heal(Afterlife::REVIVE_HITPOINTS) }
+    }
+}
+
+// Shields soak the hit, the afterlife ends the run on one, otherwise it costs a hitpoint
+fn hit_player(player: &mut Player, events: &mut EventBus, by: &Enemy, cause: DeathCause, time: f32) -> Option<RunOutcome> {
+    player.hit_by = by.clone();
+    events.emit(GameEvent::PlayerHit { amount: 1.0, by: by.kind, absorbed: player.shield });
+    if player.shield {
+        player.shield = false;
+        player.damage.damage_cooldown.cooldown_value = player.damage.damage_cooldown.cooldown;
+        return None
+    }
+    if player.supposed_to_be_dead() { return Some(player.outcome(cause, time)) }
+    player.damage.take(&DamageEvent::new(1.0, DamageType::Physical, DamageSource::Contact));
+    if player.damage.is_dead() { player.afterlife.enter() }
+    None
 }
 
-pub fn player_handler(rl: &RaylibHandle, player: &mut Player, input: &Vector2, level: &mut Level) -> i32 {
+pub fn player_handler(rl: &RaylibHandle, player: &mut Player, input: &Vector2, level: &mut Level) -> Step {
+    player.fields.speed = player.stats.get(Stat::MoveSpeed);
+    player.frenzy.cooldown_value = (player.frenzy.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
     let mut velocity: Vector2 = input.normalized().scale_by(player.fields.speed * rl.get_frame_time()).scale_by((player.damage.damage_cooldown.cooldown_value / (5.0 * 720.0 / HEIGHT)).max(1.0));
 
+    // Dash logic; a press made during knockback or cooldown waits in the buffer
+    if rl.is_key_pressed(KeyboardKey::KEY_LEFT_SHIFT) { player.dash_input.press() }
+    player.dash_input.tick(rl.get_frame_time());
+    let dash = &mut player.dash;
+    dash.cooldown.cooldown_value = (dash.cooldown.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
+    dash.active.cooldown_value = (dash.active.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
+    if dash.cooldown.cooldown_value <= 0.0 && player.damage.damage_cooldown.cooldown_value <= 20.0 && player.dash_input.take() {
+        let direction = if input.length_sqr() > 0.0 { *input } else { player.fields.direction };
+        if direction.length_sqr() > 0.0 {
+            dash.direction = direction.normalized();
+            dash.active.cooldown_value = dash.active.cooldown;
+            dash.cooldown.cooldown_value = dash.cooldown.cooldown;
+        }
+    }
+    if dash.active.cooldown_value > 0.0 {
+        velocity = dash.direction.scale_by(player.fields.speed * dash.speed_multiplier * rl.get_frame_time());
+        dash.trail.push(Afterimage { position: player.fields.position, fade: Cooldown { cooldown: 2.0, cooldown_value: 2.0 } });
+    }
+    dash.trail.retain_mut(|image| {
+        image.fade.cooldown_value -= 10.0 * rl.get_frame_time();
+        image.fade.cooldown_value > 0.0
+    });
+
     // Leveling logic
-    if player.kill_count as f32 % 20.0 == 0.0 && player.kill_count != 0 && !level.prompt.has_chosen { level.prompt.prompt(&player) }
+    if level.draft.queued > 0 && !level.draft.open { level.draft.offer(player, &level.ability_book, &level.item_book, &mut level.rng) }
 
     // Enemy damage logic
     for enemy in &level.enemies {
         player.damage.damage_cooldown.cooldown_value = (player.damage.damage_cooldown.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
-        if player.damage.damage_cooldown.cooldown_value <= 0.0 && check_collision_circles(player.fields.position, player.fields.radius, enemy.fields.position, enemy.fields.radius) {
-            if player.supposed_to_be_dead() { unsafe { ffi::CloseWindow() } }
-            player.damage.hitpoint = player.damage.hitpoint.saturating_sub(1);
-            player.damage.damage_cooldown.cooldown_value = player.damage.damage_cooldown.cooldown;
-            player.hit_by = enemy.clone();
+        if player.damage.damage_cooldown.cooldown_value <= 0.0 && !player.invincible() && check_collision_circles(player.fields.position, player.fields.radius, enemy.fields.position, enemy.fields.radius)
+            && let Some(outcome) = hit_player(player, &mut level.events, enemy, DeathCause::Contact, level.director.time) {
+                return Step { shake: 0, outcome: Some(outcome), events: Vec::new() }
+        }
+    }
+    for projectile in &mut level.projectiles {
+        if projectile.reflected || !check_collision_circles(player.fields.position, player.fields.radius, projectile.fields.position, projectile.fields.radius) { continue }
+        projectile.spent = true;
+        if player.damage.damage_cooldown.cooldown_value > 0.0 || player.invincible() { continue }
+        // Knockback pushes away from the shot, not from whoever fired it
+        let mut by = projectile.shooter.clone();
+        by.fields.position = projectile.fields.position;
+        if let Some(outcome) = hit_player(player, &mut level.events, &by, DeathCause::Projectile, level.director.time) {
+            return Step { shake: 0, outcome: Some(outcome), events: Vec::new() }
         }
     }
+    if player.afterlife.tick(rl.get_frame_time()) {
+        return Step { shake: 0, outcome: Some(player.outcome(DeathCause::AfterlifeExpired, level.director.time)), events: Vec::new() }
+    }
 
     // Knockback and camera shake logic
-    let shake: i32 = if player.damage.damage_cooldown.cooldown_value > 20.0 {
+    let shake: i32 = if player.in_knockback() {
         let angle_to_enemy = player.fields.position.angle_to(player.hit_by.fields.position);
-        let mut rng = rand::rng();
         let shake_range = ((player.damage.damage_cooldown.cooldown_value - 20.0) * (HEIGHT/(720.0/20.0))) as i32;
         velocity = velocity - Vector2 {x: angle_to_enemy.cos(), y: angle_to_enemy.sin()}.scale_by(player.damage.damage_cooldown.cooldown_value / (5.0 * 720.0 / HEIGHT));
-        rng.random_range(-shake_range..=shake_range)
+        level.rng.random_range(-shake_range..=shake_range)
     } else { 0 };
     player.fields.position += velocity;
-    shake
+    for obstacle in &level.obstacles { player.fields.position = push_out_of_rect(player.fields.position, player.fields.radius, obstacle) }
+    Step { shake, outcome: None, events: Vec::new() }
+}
+
+// Moves enemy shots, lets an early swing send them back, and lets returned ones hit enemies
+pub fn projectile_handler(rl: &RaylibHandle, level: &mut Level, player: &mut Player) {
+    let sword = player.weapons[1].get_sword().unwrap();
+    let parrying = player.equipped == 1 && sword.can_parry();
+    let parry_damage = player.stats.get(Stat::SwordDamage).max(1.0) * 2.0;
+    let (enemies, events) = (&mut level.enemies, &mut level.events);
+    level.projectiles.retain_mut(|projectile| {
+        projectile.fields.position += projectile.fields.direction.scale_by(projectile.fields.speed * rl.get_frame_time());
+        if !projectile.reflected && parrying && sword.fields.check_collision_circle_recpro(projectile.fields.position, projectile.fields.radius) {
+            projectile.reflected = true;
+            projectile.fields.direction = (projectile.shooter.fields.position - projectile.fields.position).normalized();
+            projectile.fields.speed *= 1.5;
+            projectile.fields.color = Color::SKYBLUE;
+        }
+        if projectile.reflected
+            && let Some(enemy) = enemies.iter_mut().find(|enemy| check_collision_circles(enemy.fields.position, enemy.fields.radius, projectile.fields.position, projectile.fields.radius)) {
+                enemy.hurt(&DamageEvent::new(parry_damage, DamageType::Physical, DamageSource::Sword), events);
+                projectile.spent = true;
+        }
+        let position = projectile.fields.position;
+        let inside = position.x >= 0.0 && position.x <= WIDTH && position.y >= 0.0 && position.y <= HEIGHT;
+        !projectile.spent && inside && !level.obstacles.iter().any(|obstacle| obstacle.check_collision_circle_rec(position, projectile.fields.radius))
+    });
 }
 
 pub fn enemy_handler(rl: &RaylibHandle, level: &mut Level, player: &mut Player) {
-    if level.enemy_cooldown.cooldown_value <= 0.0 {
-        let enemy = Enemy::new(&player.kill_count);
-        level.enemies.push(enemy);
-        if player.supposed_to_be_dead() { level.enemy_cooldown.cooldown_value = level.enemy_cooldown.cooldown / 2.0 } else { level.enemy_cooldown.cooldown_value = level.enemy_cooldown.cooldown }
+    let orders = level.director.update(rl.get_frame_time(), player.kill_count, player.level, player.afterlife.spawn_multiplier(), &mut level.rng);
+    for order in orders {
+        let mut enemy = Enemy::from_type(&player.kill_count, &level.director.enemy_types[order.enemy], &mut level.rng);
+        enemy.fields.position = order.pattern.position(order.slot, player.fields.position, player.fields.direction, &mut level.rng);
+        level.pending_spawns.push_back(PendingSpawn::new(enemy));
     }
-    level.enemy_cooldown.cooldown_value = (level.enemy_cooldown.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
 
+    // Telegraphed spawns hold off while the player stands on the marker and give up after a few tries
+    for _ in 0..level.pending_spawns.len() {
+        let mut pending = level.pending_spawns.pop_front().unwrap();
+        pending.telegraph.cooldown_value = (pending.telegraph.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
+        if pending.telegraph.cooldown_value > 0.0 { level.pending_spawns.push_back(pending); continue }
+        if check_collision_circles(player.fields.position, player.fields.radius, pending.enemy.fields.position, pending.enemy.fields.radius) {
+            if pending.delays < PendingSpawn::MAX_DELAYS {
+                pending.delays += 1;
+                pending.telegraph.cooldown_value = pending.telegraph.cooldown / 2.0;
+                level.pending_spawns.push_back(pending);
+            }
+            continue
+        }
+        level.spawn(pending.enemy);
+    }
+
+    // One shared flow field towards the player (or a decoy), rebuilt a few times per second
+    let target = decoy(&level.field_effects).unwrap_or(player.fields.position);
+    if level.flow_cooldown.cooldown_value <= 0.0 {
+        level.flow_field.rebuild(&level.nav, target);
+        level.flow_cooldown.cooldown_value = level.flow_cooldown.cooldown;
+    }
+    level.flow_cooldown.cooldown_value = (level.flow_cooldown.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
+    level.paths.process(&level.nav);
+
+    let (crit_chance, crit_multiplier) = (player.stats.get(Stat::CritChance), player.stats.get(Stat::CritMultiplier));
+    let (gun_damage, sword_damage) = (player.stats.get(Stat::GunDamage), player.stats.get(Stat::SwordDamage));
     level.enemies.retain_mut(|enemy| {
         let gun = player.weapons[0].get_gun_mut().unwrap();
         enemy.damage.damage_cooldown.cooldown_value = (enemy.damage.damage_cooldown.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
-        if enemy.damage.damage_cooldown.cooldown_value <= 0.0
+        if enemy.damage.can_be_hit()
             && gun.bullets
                     .iter_mut()
                     .any(|bullet| { 
                         if check_collision_circles(enemy.fields.position, enemy.fields.radius, bullet.fields.position, bullet.fields.radius) {
-                            bullet.hit_enemy = true;
+                            // Piercing bullets spend a charge instead of stopping
+                            if bullet.pierce > 0 { bullet.pierce -= 1 } else { bullet.hit_enemy = true }
                             true
                         } else { false }
                     }) {
-                enemy.damage.hitpoint -= 1;
-                enemy.damage.damage_cooldown.cooldown_value = enemy.damage.damage_cooldown.cooldown;
-                enemy.fields.radius -= HEIGHT/180.0;
+                let event = DamageEvent::new(gun_damage, DamageType::Physical, DamageSource::Gun).roll_crit(crit_chance, crit_multiplier, &mut level.rng);
+                enemy.hurt(&event, &mut level.events);
+                for &effect in &gun.effects { enemy.status.apply(effect) }
         }
 
         let sword = player.weapons[1].get_sword_mut().unwrap();
-        if enemy.damage.damage_cooldown.cooldown_value <= 0.0
+        if enemy.damage.can_be_hit()
             && (sword.fields.check_collision_circle_recpro(enemy.fields.position, enemy.fields.radius) && player.equipped == 1 && sword.is_swinging) {
-                enemy.damage.hitpoint = enemy.damage.hitpoint.saturating_sub(sword.damage.ceil() as u8);
-                enemy.damage.damage_cooldown.cooldown_value = enemy.damage.damage_cooldown.cooldown;
-                enemy.fields.radius -= 4.0 * sword.damage;
+                let event = DamageEvent::new(sword_damage * sword.swing.damage(), DamageType::Physical, DamageSource::Sword).roll_crit(crit_chance, crit_multiplier, &mut level.rng);
+                enemy.hurt(&event, &mut level.events);
+                for &effect in &sword.effects { enemy.status.apply(effect) }
         }
+
+        // Status ticks go through the same pipeline, so bosses shrug off half of a burn
+        for event in enemy.status.update(rl.get_frame_time()) { enemy.hurt(&event, &mut level.events) }
         
-        let angle_dir = enemy.fields.position.angle_to(player.fields.position);
-        let velocity = Vector2::scale_by(&enemy.fields.direction.normalized(), enemy.fields.speed * rl.get_frame_time());
-        enemy.fields.direction = Vector2 { x: (angle_dir.cos()), y: (angle_dir.sin()) };
+        let angle_dir = enemy.fields.position.angle_to(target);
+        let time_scale = time_scale_at(&level.field_effects, enemy.fields.position);
+        let velocity = Vector2::scale_by(&enemy.fields.direction.normalized(), enemy.fields.speed * enemy.status.speed_multiplier() * time_scale * rl.get_frame_time());
+        // Off the grid or pushed into an obstacle, head for open ground first; only a pocket the field never reached goes straight at the target
+        let flow_direction = level.flow_field
+            .direction_at(&level.nav, enemy.fields.position)
+            .or_else(|| level.nav.nearest_open_point(enemy.fields.position, 4)
+                .filter(|open| open.distance_to(enemy.fields.position) > CELL_SIZE / 4.0)
+                .map(|open| (open - enemy.fields.position).normalized()))
+            .unwrap_or(Vector2 { x: (angle_dir.cos()), y: (angle_dir.sin()) });
+
+        // Elites and bosses plan their own routes: flank around obstacles, fall back to cover when hurt
+        if enemy.is_smart() {
+            if let Some(path) = level.paths.take(enemy.id) { enemy.path = path.unwrap_or_default() }
+            if enemy.hit_this_tick && enemy.kind == EnemyKind::Elite && enemy.damage.hitpoint * 2.0 <= enemy.max_hitpoint {
+                enemy.retreat.cooldown_value = enemy.retreat.cooldown;
+                enemy.repath.cooldown_value = 0.0;
+            }
+            enemy.repath.cooldown_value = (enemy.repath.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
+            enemy.retreat.cooldown_value = (enemy.retreat.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
+            if enemy.repath.cooldown_value <= 0.0 && !level.paths.is_pending(enemy.id) {
+                let goal = if enemy.retreat.cooldown_value > 0.0 {
+                    level.nav.cover_point(enemy.fields.position, player.fields.position, 8).unwrap_or(enemy.fields.position)
+                } else {
+                    level.nav.flank_point(enemy.fields.position, target, CELL_SIZE * 4.0).unwrap_or(target)
+                };
+                level.paths.request(enemy.id, enemy.fields.position, goal);
+                enemy.repath.cooldown_value = enemy.repath.cooldown;
+            }
+            while enemy.path.first().is_some_and(|waypoint| waypoint.distance_to(enemy.fields.position) < CELL_SIZE / 2.0) { enemy.path.remove(0); }
+        }
+        // Shots need a clear line and some range, and wait while the enemy is frozen
+        if let Some(shot) = &mut enemy.shot {
+            shot.cooldown_value = (shot.cooldown_value - 10.0 * enemy.status.speed_multiplier() * rl.get_frame_time()).max(0.0);
+            if shot.cooldown_value <= 0.0 && enemy.fields.position.distance_to(target) < CELL_SIZE * 12.0 && level.nav.line_of_sight(enemy.fields.position, target) {
+                shot.cooldown_value = shot.cooldown;
+                level.projectiles.push(Projectile::new(enemy, target));
+            }
+        }
+        let charging = enemy.retreat.cooldown_value <= 0.0
+            && enemy.fields.position.distance_to(target) < CELL_SIZE * 5.0
+            && level.nav.line_of_sight(enemy.fields.position, target);
+        enemy.fields.direction = match enemy.path.first() {
+            Some(waypoint) if !charging => (*waypoint - enemy.fields.position).normalized(),
+            _ => flow_direction
+        };
+        enemy.hit_this_tick = false;
         enemy.fields.position = Vector2::add(enemy.fields.position, velocity);
+        for obstacle in &level.obstacles { enemy.fields.position = push_out_of_rect(enemy.fields.position, enemy.fields.radius, obstacle) }
 
-        if enemy.damage.hitpoint > 0 { true } else { 
-            player.kill_count += 1;
-            level.prompt.has_chosen = false;
+        if !enemy.damage.is_dead() { true } else { 
+            level.paths.cancel(enemy.id);
+            level.events.emit(GameEvent::EnemyKilled { id: enemy.id, kind: enemy.kind, position: enemy.fields.position, experience: enemy.experience });
             false 
         }});
 }
\ No newline at end of file
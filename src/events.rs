@@ -0,0 +1,72 @@
+use crate::damage::DamageSource;
+use crate::entities::EnemyKind;
+use crate::pickups::PickupKind;
+use raylib::prelude::*;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WeaponKind {
+    Gun,
+    Sword
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GameEvent {
+    EnemyDamaged { id: u64, position: Vector2, amount: f32, crit: bool, source: DamageSource },
+    EnemyKilled { id: u64, kind: EnemyKind, position: Vector2, experience: u32 },
+    PlayerHit { amount: f32, by: EnemyKind, absorbed: bool },
+    WeaponFired { weapon: WeaponKind, position: Vector2 },
+    LevelUp { level: u128 },
+    PickupCollected { kind: PickupKind, position: Vector2 }
+}
+
+// Events pile up here while systems run and are drained once per tick
+pub struct EventBus {
+    pub queue: Vec<GameEvent>
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self { queue: Vec::new() }
+    }
+    pub fn emit(&mut self, event: GameEvent) {
+        self.queue.push(event);
+    }
+    pub fn drain(&mut self) -> Vec<GameEvent> {
+        std::mem::take(&mut self.queue)
+    }
+}
+
+// Anything outside the simulation that wants to react to events, such as audio or achievements
+pub trait Subscriber {
+    fn notify(&mut self, event: &GameEvent);
+}
+
+// Tallies a run for the results screen purely from events
+#[derive(Default)]
+pub struct CombatLog {
+    pub shots: u32,
+    pub swings: u32,
+    pub damage_dealt: f32,
+    pub crits: u32,
+    pub kills: [u32; 3],
+    pub hits_taken: u32,
+    pub hits_absorbed: u32,
+    pub pickups: u32
+}
+
+impl Subscriber for CombatLog {
+    fn notify(&mut self, event: &GameEvent) {
+        match *event {
+            GameEvent::EnemyDamaged { amount, crit, .. } => {
+                self.damage_dealt += amount;
+                if crit { self.crits += 1 }
+            }
+            GameEvent::EnemyKilled { kind, .. } => self.kills[kind as usize] += 1,
+            GameEvent::PlayerHit { absorbed, .. } => if absorbed { self.hits_absorbed += 1 } else { self.hits_taken += 1 },
+            GameEvent::WeaponFired { weapon: WeaponKind::Gun, .. } => self.shots += 1,
+            GameEvent::WeaponFired { weapon: WeaponKind::Sword, .. } => self.swings += 1,
+            GameEvent::PickupCollected { .. } => self.pickups += 1,
+            GameEvent::LevelUp { .. } => {}
+        }
+    }
+}
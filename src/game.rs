@@ -0,0 +1,325 @@
+use crate::entities::{Player, Level, RunOutcome, DeathCause, EnemyKind, simulate};
+use crate::state::{GameState, StateEvent};
+use crate::draft::{draft_handler, draw_draft};
+use crate::stats::Stat;
+use crate::pickups::{PickupKind, experience_to_next};
+use crate::abilities::AbilityEffect;
+use crate::weapons::{FireMode, Sword};
+use crate::aim::{Aim, TargetRule};
+use crate::utils::damp;
+use crate::events::{CombatLog, Subscriber};
+use crate::{WIDTH, HEIGHT, SCREEN_WIDTH, SCREEN_HEIGHT, VIRTUAL_RATIO, CAMERA_ZOOM};
+use raylib::{color::Color, prelude::*};
+use std::{ops::Add};
+
+// Seconds for the camera to close half the distance to where it wants to be
+const CAMERA_HALF_LIFE: f32 = 0.085;
+
+pub struct Game {
+    pub state: GameState,
+    pub player: Player,
+    pub level: Level,
+    pub camera: Camera2D,
+    pub screen_camera: Camera2D,
+    pub camera_lookahead: f32,
+    pub outcome: Option<RunOutcome>,
+    pub log: CombatLog,
+    pub quit: bool
+}
+
+impl Game {
+    pub fn new() -> Self {
+        let player = Player::new();
+        Self {
+            state: GameState::Title,
+            camera: Camera2D {
+                offset: Vector2 { x: WIDTH/2.0, y: HEIGHT/2.0},
+                target: player.fields.position,
+                rotation: 0.0,
+                zoom: CAMERA_ZOOM },
+            screen_camera: Camera2D {
+                offset: Vector2 { x: 0.0, y: 0.0},
+                target: player.fields.position,
+                rotation: 0.0,
+                zoom: 1.0 },
+            camera_lookahead: 12.5,
+            player,
+            level: Level::new(),
+            outcome: None,
+            log: CombatLog::default(),
+            quit: false
+        }
+    }
+
+    pub fn handle(&mut self, event: StateEvent) {
+        // Decided before any reset, which would otherwise put us back on the title screen
+        let next = self.state.next(event);
+        if self.state.starts_run(event) {
+            let (quit, aim) = (self.quit, self.player.aim);
+            *self = Self::new();
+            self.quit = quit;
+            self.player.aim = Aim { target: None, ..aim };
+        }
+        self.state = next;
+    }
+
+    pub fn update(&mut self, rl: &mut RaylibHandle) {
+        // Aim settings can be changed from anywhere but the level-up screen
+        if self.state != GameState::LevelUp {
+            let aim = &mut self.player.aim;
+            if rl.is_key_pressed(KeyboardKey::KEY_V) { aim.mode = aim.mode.next() }
+            if rl.is_key_pressed(KeyboardKey::KEY_B) { aim.rule = if aim.rule == TargetRule::Threat { TargetRule::Nearest } else { TargetRule::Threat } }
+        }
+        let event = match self.state {
+            GameState::Title => self.update_title(rl),
+            GameState::Playing => self.update_playing(rl),
+            GameState::Paused => self.update_paused(rl),
+            GameState::LevelUp => self.update_level_up(rl),
+            GameState::GameOver => self.update_game_over(rl),
+            GameState::Results => self.update_results(rl)
+        };
+        if let Some(event) = event { self.handle(event) }
+    }
+
+    fn update_title(&mut self, rl: &RaylibHandle) -> Option<StateEvent> {
+        if rl.is_key_pressed(KeyboardKey::KEY_ESCAPE) { self.quit = true }
+        if rl.is_key_pressed(KeyboardKey::KEY_ENTER) || rl.is_key_pressed(KeyboardKey::KEY_SPACE) { Some(StateEvent::Start) } else { None }
+    }
+
+    fn update_playing(&mut self, rl: &RaylibHandle) -> Option<StateEvent> {
+        if rl.is_key_pressed(KeyboardKey::KEY_ESCAPE) || rl.is_key_pressed(KeyboardKey::KEY_P) { return Some(StateEvent::Pause) }
+        let (player, level) = (&mut self.player, &mut self.level);
+
+        let mut input = Vector2::new(0.0, 0.0);
+        if !player.in_knockback() {
+            if rl.is_key_down(KeyboardKey::KEY_W) { input.y -= 1.0; }
+            if rl.is_key_down(KeyboardKey::KEY_A) { input.x -= 1.0; }
+            if rl.is_key_down(KeyboardKey::KEY_S) { input.y += 1.0; }
+            if rl.is_key_down(KeyboardKey::KEY_D) { input.x += 1.0; }
+        }
+
+        let step = simulate(rl, player, &input, level);
+        let shake = step.shake;
+        for event in &step.events { self.log.notify(event) }
+
+        self.screen_camera.target = Vector2 {
+            x: damp(self.camera.target.x, player.fields.position.x.add(player.fields.direction.x * self.camera_lookahead + shake as f32), CAMERA_HALF_LIFE, rl.get_frame_time()),
+            y: damp(self.camera.target.y, player.fields.position.y.add(player.fields.direction.y * self.camera_lookahead + shake as f32), CAMERA_HALF_LIFE, rl.get_frame_time())
+        };
+
+        self.camera.target = Vector2 { x: self.screen_camera.target.x.trunc(), y: self.screen_camera.target.y.trunc() };
+        self.screen_camera.target -= self.camera.target;
+        self.screen_camera.target *= VIRTUAL_RATIO;
+
+        if step.outcome.is_some() {
+            self.outcome = step.outcome;
+            Some(StateEvent::PlayerDied)
+        } else if level.draft.open { Some(StateEvent::LevelUpOffered) }
+        else { None }
+    }
+
+    fn update_paused(&mut self, rl: &RaylibHandle) -> Option<StateEvent> {
+        if rl.is_key_pressed(KeyboardKey::KEY_ESCAPE) || rl.is_key_pressed(KeyboardKey::KEY_P) { Some(StateEvent::Resume) }
+        else if rl.is_key_pressed(KeyboardKey::KEY_Q) { Some(StateEvent::ToTitle) }
+        else { None }
+    }
+
+    fn update_level_up(&mut self, rl: &RaylibHandle) -> Option<StateEvent> {
+        draft_handler(rl, &mut self.level, &mut self.player);
+        if !self.level.draft.open { Some(StateEvent::LevelUpChosen) } else { None }
+    }
+
+    fn update_game_over(&mut self, rl: &RaylibHandle) -> Option<StateEvent> {
+        if rl.is_key_pressed(KeyboardKey::KEY_ENTER) || rl.is_key_pressed(KeyboardKey::KEY_SPACE) { Some(StateEvent::Continue) } else { None }
+    }
+
+    fn update_results(&mut self, rl: &RaylibHandle) -> Option<StateEvent> {
+        if rl.is_key_pressed(KeyboardKey::KEY_R) || rl.is_key_pressed(KeyboardKey::KEY_ENTER) { Some(StateEvent::Restart) }
+        else if rl.is_key_pressed(KeyboardKey::KEY_ESCAPE) || rl.is_key_pressed(KeyboardKey::KEY_T) { Some(StateEvent::ToTitle) }
+        else { None }
+    }
+
+    pub fn background(&self) -> Color {
+        self.player.afterlife.palette().background
+    }
+
+    // World space, drawn through `camera` into the low resolution render target
+    pub fn draw_world<D: RaylibDraw>(&self, d: &mut D) {
+        let (player, level) = (&self.player, &self.level);
+        let palette = player.afterlife.palette();
+        for image in &player.dash.trail {
+            d.draw_circle_v(image.position, player.fields.radius, palette.player_text.alpha(0.4 * image.fade.cooldown_value / image.fade.cooldown));
+        }
+        if !player.supposed_to_be_dead() {
+        d.draw_circle_v(player.fields.position, player.fields.radius, player.fields.color.tint(Color { r: (player.damage.damage_cooldown.cooldown_value * 8.0).min(255.0).round() as u8, g: 0, b: 0, a: 255 }));
+        d.draw_text(format!("{}", player.damage.hitpoint.ceil()).as_str(), player.fields.position.x as i32, player.fields.position.y as i32, (HEIGHT/36.0).round() as i32, Color::RED);
+        d.draw_text(format!("{:02}", player.kill_count).as_str(), (player.fields.position.x - HEIGHT/(720.0/12.0)).round() as i32, (player.fields.position.y + HEIGHT/(720.0/25.0)).round() as i32, (HEIGHT/(720.0/15.0)).round() as i32, Color::RED);
+        } else {
+            d.draw_circle_v(player.fields.position, player.fields.radius, Color::WHITE);
+            d.draw_text("YOU ARE SUPPOSED TO BE DEAD.", (player.fields.position.x - HEIGHT/(720.0/220.0)).round() as i32, (player.fields.position.y - HEIGHT/(720.0/50.0)).round() as i32, (HEIGHT/(720.0/15.0)).round() as i32, palette.player_text);
+            d.draw_text(format!("{:02}/{:02}", player.afterlife.kills, player.afterlife.kills_to_revive).as_str(), (player.fields.position.x - HEIGHT/(720.0/24.0)).round() as i32, (player.fields.position.y + HEIGHT/(720.0/25.0)).round() as i32, (HEIGHT/(720.0/15.0)).round() as i32, palette.player_text);
+            // The ring shrinks as the afterlife runs out
+            d.draw_ring(player.fields.position, player.fields.radius * 1.4, player.fields.radius * 1.6, 0.0, 360.0 * player.afterlife.remaining(), 36, palette.player_text);
+        }
+        if player.shield { d.draw_circle_lines_v(player.fields.position, player.fields.radius * 1.25, Color::BLUE) }
+        // Reload bar with its active reload window, or the heat meter, under the player
+        let bar = Rectangle { x: player.fields.position.x - player.fields.radius, y: player.fields.position.y + player.fields.radius * 1.5, width: player.fields.radius * 2.0, height: player.fields.radius / 4.0 };
+        match &player.weapons[0].get_gun().unwrap().mode {
+            FireMode::Magazine(magazine) if magazine.reloading => {
+                d.draw_rectangle_rec(bar, Color::DARKGRAY);
+                d.draw_rectangle_rec(Rectangle { x: bar.x + bar.width * magazine.window.0, width: bar.width * (magazine.window.1 - magazine.window.0), ..bar }, if magazine.attempted { Color::GRAY } else { Color::LIME });
+                d.draw_rectangle_rec(Rectangle { x: bar.x + bar.width * magazine.progress() - 1.0, width: 2.0, ..bar }, Color::WHITE);
+            }
+            FireMode::Magazine(magazine) => {
+                let pip = bar.width / magazine.size as f32;
+                for round in 0..magazine.rounds { d.draw_rectangle_rec(Rectangle { x: bar.x + round as f32 * pip, width: pip * 0.7, ..bar }, Color::GOLD) }
+            }
+            FireMode::Overheat(overheat) if overheat.heat > 0.0 => {
+                d.draw_rectangle_rec(bar, Color::DARKGRAY);
+                d.draw_rectangle_rec(Rectangle { width: bar.width * overheat.heat, ..bar }, if overheat.overheated { Color::RED } else { Color::ORANGE });
+            }
+            _ => {}
+        }
+        if player.frenzy.cooldown_value > 0.0 {
+            d.draw_ring(player.fields.position, player.fields.radius * 1.1, player.fields.radius * 1.2, 0.0, 360.0 * player.frenzy.cooldown_value / player.frenzy.cooldown, 36, Color::ORANGE);
+        }
+
+        let (gun, sword) = &(player.weapons[0].get_gun().unwrap(), player.weapons[1].get_sword().unwrap());
+
+        match player.equipped {
+            0 => d.draw_rectangle_pro(gun.fields.rect, gun.fields.origin, gun.fields.rotation, gun.fields.color),
+            1 => d.draw_rectangle_pro(sword.fields.rect, sword.fields.origin, sword.fields.rotation, sword.fields.color),
+            _ => {}
+        }
+        // Charge ring fills while the swing button is held, gold once a spin is ready
+        if player.equipped == 1 && sword.charge > 0.1 {
+            let charge = (sword.charge / Sword::CHARGE_TIME).min(1.0);
+            d.draw_ring(player.fields.position, player.fields.radius * 1.3, player.fields.radius * 1.4, 0.0, 360.0 * charge, 36, if charge >= 1.0 { Color::GOLD } else { Color::LIGHTGRAY });
+        }
+
+        for obstacle in &level.obstacles {
+            d.draw_rectangle_rec(obstacle, palette.obstacle);
+        }
+        for pickup in level.pickups.iter().filter(|pickup| pickup.visible(level.director.time)) {
+            let color = palette.enemy.map_or(pickup.fields.color, |_| palette.player_text);
+            match pickup.kind {
+                PickupKind::Experience(_) => d.draw_poly(pickup.fields.position, 4, pickup.fields.radius, 45.0, color),
+                PickupKind::Shield => d.draw_ring(pickup.fields.position, pickup.fields.radius * 0.6, pickup.fields.radius, 0.0, 360.0, 24, color),
+                _ => d.draw_circle_v(pickup.fields.position, pickup.fields.radius, color)
+            }
+        }
+        for field in &level.field_effects {
+            match field.effect {
+                AbilityEffect::Shockwave => d.draw_circle_lines_v(field.position, field.radius * field.progress(), palette.border),
+                AbilityEffect::TimeSlow => d.draw_circle_v(field.position, field.radius, Color::SKYBLUE.alpha(0.25)),
+                AbilityEffect::Decoy => {
+                    d.draw_circle_v(field.position, player.fields.radius, player.fields.color.alpha(0.6));
+                    d.draw_circle_lines_v(field.position, player.fields.radius, palette.player_text);
+                }
+            }
+        }
+        for bullet in &gun.bullets { d.draw_circle_v(bullet.fields.position, bullet.fields.radius, bullet.fields.color); }
+        for projectile in &level.projectiles { d.draw_circle_v(projectile.fields.position, projectile.fields.radius, projectile.fields.color) }
+        for pending in &level.pending_spawns {
+            let enemy = &pending.enemy;
+            let pulse = (pending.progress() * 6.0 * std::f32::consts::PI).sin().abs();
+            let color = palette.enemy.unwrap_or(enemy.fields.color);
+            d.draw_circle_v(enemy.fields.position, enemy.fields.radius * (0.6 + 0.4 * pending.progress()), Color { a: (40.0 + 60.0 * pulse) as u8, ..color });
+            d.draw_circle_lines_v(enemy.fields.position, enemy.fields.radius, color);
+            // Most spawns land outside the view, so their warning is an arrow on its edge pointing at the marker
+            let (half, margin) = (Vector2 { x: WIDTH / (2.0 * self.camera.zoom), y: HEIGHT / (2.0 * self.camera.zoom) }, HEIGHT / 30.0);
+            let offset = enemy.fields.position - self.camera.target;
+            if offset.x.abs() > half.x || offset.y.abs() > half.y {
+                let edge = self.camera.target + Vector2 { x: offset.x.clamp(margin - half.x, half.x - margin), y: offset.y.clamp(margin - half.y, half.y - margin) };
+                d.draw_poly(edge, 3, margin * (0.6 + 0.4 * pulse), offset.y.atan2(offset.x).to_degrees(), color);
+            }
+        }
+        for enemy in &level.enemies {
+            d.draw_circle_v(enemy.fields.position, enemy.fields.radius, palette.enemy.unwrap_or(enemy.fields.color));
+            if player.aim.target == Some(enemy.id) { d.draw_circle_lines_v(enemy.fields.position, enemy.fields.radius * 1.3, palette.player_text) }
+            if let Some(tint) = enemy.status.tint() { d.draw_circle_v(enemy.fields.position, enemy.fields.radius, tint.alpha(0.5)) }
+            d.draw_text(format!("{}", enemy.damage.hitpoint.ceil()).as_str(), enemy.fields.position.x as i32, enemy.fields.position.y as i32, (HEIGHT/36.0).round() as i32, palette.enemy_text);
+        }
+        d.draw_rectangle_lines_ex(Rectangle {x: 0.0, y:0.0, width: WIDTH, height: HEIGHT}, 5.0, palette.border);
+        if player.supposed_to_be_dead() {
+            d.draw_circle_gradient(player.fields.position.x as i32, player.fields.position.y as i32, 300.0, Color {r: 255, g: 255, b: 255, a:100}, Color {r: 255, g: 0, b: 0, a:0});
+        }
+    }
+
+    // Screen space, drawn on top of the upscaled world
+    pub fn draw_overlay(&self, d: &mut RaylibDrawHandle) {
+        let (gun, sword) = &(self.player.weapons[0].get_gun().unwrap(), self.player.weapons[1].get_sword().unwrap());
+        let palette = self.player.afterlife.palette();
+        d.draw_text(format!("Gun({}) Cooldown: {:.1?}, Sword({}) Damage: {:.1?}", gun.level, self.player.stats.get(Stat::ReloadTime), sword.level, self.player.stats.get(Stat::SwordDamage)).as_str(), 20, SCREEN_HEIGHT as i32-25, 20, palette.border);
+        d.draw_text(format!("SCORE {}", self.player.score).as_str(), 20, 20, 20, palette.border);
+        let progress = self.player.experience as f32 / experience_to_next(self.player.level + self.level.draft.queued as u128) as f32;
+        d.draw_rectangle(0, 0, (SCREEN_WIDTH * progress.min(1.0)) as i32, 8, Color::SKYBLUE);
+        d.draw_text(format!("LV {}", self.player.level + 1).as_str(), SCREEN_WIDTH as i32 - 80, 20, 20, palette.border);
+        // Ability slots sweep clockwise back to full as they recharge
+        for (index, slot) in self.player.abilities.iter().enumerate() {
+            let center = Vector2 { x: SCREEN_WIDTH - 60.0 - index as f32 * 80.0, y: SCREEN_HEIGHT - 70.0 };
+            d.draw_circle_v(center, 30.0, Color::DARKGRAY.alpha(0.6));
+            d.draw_circle_sector(center, 30.0, -90.0, -90.0 + 360.0 * (1.0 - slot.charge()), 36, if slot.charge() <= 0.0 { Color::GOLD } else { Color::GRAY });
+            d.draw_text(format!("{}", index + 1).as_str(), center.x as i32 - 5, center.y as i32 - 10, 20, Color::BLACK);
+            let width = d.measure_text(&slot.ability.name, 16);
+            d.draw_text(&slot.ability.name, center.x as i32 - width / 2, center.y as i32 + 34, 16, palette.border);
+        }
+        // Items with their stacks, then any synergies they complete
+        let items = &self.player.items;
+        for (index, owned) in items.items.iter().enumerate() {
+            d.draw_text(format!("{} x{}", owned.item.name, owned.stacks).as_str(), 20, 50 + index as i32 * 20, 16, palette.border);
+        }
+        for (index, synergy) in items.active_synergies(&self.level.item_book.synergies).enumerate() {
+            d.draw_text(format!("{} x{:.1}", synergy.name, synergy.bonus).as_str(), 20, 60 + (items.items.len() + index) as i32 * 20, 16, Color::GOLD);
+        }
+        for (index, error) in self.level.load_errors.iter().enumerate() {
+            d.draw_text(error, 20, SCREEN_HEIGHT as i32 - 50 - index as i32 * 20, 16, Color::RED);
+        }
+
+        let (title, hint) = match self.state {
+            GameState::Title => ("RAYLIB TEST".to_string(), format!("ENTER to start, ESC to quit. Aim {} (V), targets {} (B)", self.player.aim.mode.name(), self.player.aim.rule.name())),
+            GameState::Paused => ("PAUSED".to_string(), format!("P to resume, Q to quit to title. Aim {} (V), targets {} (B)", self.player.aim.mode.name(), self.player.aim.rule.name())),
+            GameState::GameOver => {
+                let reason = match self.outcome.as_ref().map(|outcome| (outcome.cause, outcome.hit_by.kind)) {
+                    Some((DeathCause::Contact, EnemyKind::Grunt)) => "Caught by a grunt",
+                    Some((DeathCause::Contact, EnemyKind::Elite)) => "Caught by an elite",
+                    Some((DeathCause::Contact, EnemyKind::Boss)) => "Caught by a boss",
+                    Some((DeathCause::Projectile, _)) => "Shot down",
+                    Some((DeathCause::AfterlifeExpired, _)) => "Borrowed time ran out",
+                    None => "Caught by something"
+                };
+                ("GAME OVER".to_string(), format!("{reason}. ENTER to continue"))
+            }
+            GameState::Results => match &self.outcome {
+                Some(outcome) => (
+                    format!("SCORE {}  KILLS {}  LEVEL {}  TIME {:.0}s", outcome.score, outcome.kills, outcome.level, outcome.time_survived),
+                    format!(
+                        "AFTERLIFE: {} entered, {} revived, {} kills for {} points in {:.0}s. R to restart, T for title",
+                        outcome.afterlife.entered, outcome.afterlife.revived, outcome.afterlife.kills, outcome.afterlife.score, outcome.afterlife.time
+                    )
+                ),
+                None => ("RESULTS".to_string(), "R to restart, T for title".to_string())
+            },
+            GameState::LevelUp => ("LEVEL UP".to_string(), String::new()),
+            GameState::Playing => return
+        };
+        d.draw_rectangle(0, 0, SCREEN_WIDTH as i32, SCREEN_HEIGHT as i32, Color { r: 0, g: 0, b: 0, a: 150 });
+        let (title_width, hint_width) = (d.measure_text(&title, 60), d.measure_text(&hint, 24));
+        if self.state == GameState::LevelUp {
+            d.draw_text(&title, (SCREEN_WIDTH as i32 - title_width) / 2, SCREEN_HEIGHT as i32 / 8, 60, Color::WHITE);
+            draw_draft(d, &self.level.draft, &self.level.animations);
+            return
+        }
+        d.draw_text(&title, (SCREEN_WIDTH as i32 - title_width) / 2, SCREEN_HEIGHT as i32 / 2 - 60, 60, Color::WHITE);
+        d.draw_text(&hint, (SCREEN_WIDTH as i32 - hint_width) / 2, SCREEN_HEIGHT as i32 / 2 + 20, 24, Color::WHITE);
+        if self.state == GameState::Results {
+            let log = &self.log;
+            let line = format!(
+                "{} shots, {} swings, {:.0} damage, {} crits, {}/{}/{} kills, {} hits taken, {} absorbed, {} pickups",
+                log.shots, log.swings, log.damage_dealt, log.crits, log.kills[0], log.kills[1], log.kills[2], log.hits_taken, log.hits_absorbed, log.pickups
+            );
+            let width = d.measure_text(&line, 20);
+            d.draw_text(&line, (SCREEN_WIDTH as i32 - width) / 2, SCREEN_HEIGHT as i32 / 2 + 60, 20, Color::LIGHTGRAY);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use crate::data::{self, Loader};
+use crate::damage::{DamageEvent, DamageSource, DamageType};
+use crate::entities::{Level, Player};
+use crate::events::{EventBus, GameEvent, WeaponKind};
+use crate::weapons::Bullet;
+use crate::HEIGHT;
+use rand::{Rng, rngs::StdRng};
+
+const ITEM_FILE: &str = "assets/items.txt";
+const DEFAULT_ITEMS: &str = include_str!("../assets/items.txt");
+
+// The points in the combat flow an item can hook into
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Trigger {
+    Kill,
+    Hit,
+    Hurt,
+    Shot
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ItemEffect {
+    Heal,
+    Chain,
+    Nova,
+    Pierce
+}
+
+#[derive(Clone)]
+pub struct ItemDef {
+    pub name: String,
+    pub trigger: Trigger,
+    pub effect: ItemEffect,
+    pub chance: f32,
+    pub every: u32,
+    pub amount: f32,
+    pub radius: f32,
+    pub priority: i32,
+    pub weight: u32
+}
+
+// Owning every listed item multiplies their amounts by `bonus`
+pub struct Synergy {
+    pub name: String,
+    pub items: Vec<String>,
+    pub bonus: f32
+}
+
+pub struct ItemBook {
+    pub items: Vec<ItemDef>,
+    pub synergies: Vec<Synergy>
+}
+
+impl ItemBook {
+    pub fn load(loader: &mut Loader) -> Self {
+        loader.load(ITEM_FILE, DEFAULT_ITEMS, Self::parse)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut book = Self { items: Vec::new(), synergies: Vec::new() };
+        for mut line in data::lines(source) {
+            let entry = line.keyword;
+            if entry != "item" && entry != "synergy" { return Err(line.unknown()) }
+            let name = line.name()?;
+            let fields = line.fields()?;
+
+            if entry == "synergy" {
+                let items: Vec<String> = fields.field("items").ok_or(fields.error("synergy needs items"))?.split(',').map(str::to_string).collect();
+                if let Some(missing) = items.iter().find(|item| !book.items.iter().any(|def| def.name == **item)) {
+                    return Err(fields.error(format!("synergy names unknown item `{missing}`")))
+                }
+                book.synergies.push(Synergy { name: name.to_string(), items, bonus: fields.number_field("bonus", 1.5)? });
+                continue
+            }
+            let trigger = match fields.field("trigger").ok_or(fields.error("item needs a trigger"))? {
+                "kill" => Trigger::Kill,
+                "hit" => Trigger::Hit,
+                "hurt" => Trigger::Hurt,
+                "shot" => Trigger::Shot,
+                other => return Err(fields.error(format!("unknown trigger `{other}`")))
+            };
+            let effect = match fields.field("effect").ok_or(fields.error("item needs an effect"))? {
+                "heal" => ItemEffect::Heal,
+                "chain" => ItemEffect::Chain,
+                "nova" => ItemEffect::Nova,
+                "pierce" => ItemEffect::Pierce,
+                other => return Err(fields.error(format!("unknown effect `{other}`")))
+            };
+            book.items.push(ItemDef {
+                name: name.to_string(),
+                trigger,
+                effect,
+                chance: fields.number_field("chance", 1.0)?,
+                every: fields.number_field("every", 1.0)?.max(1.0) as u32,
+                amount: fields.number_field("amount", 1.0)?,
+                radius: fields.number_field("radius", 0.1)? * HEIGHT,
+                priority: fields.number_field("priority", 0.0)? as i32,
+                weight: fields.number_field("weight", 1.0)? as u32
+            });
+        }
+        Ok(book)
+    }
+}
+
+pub struct OwnedItem {
+    pub item: ItemDef,
+    pub stacks: u32,
+    pub counter: u32
+}
+
+impl OwnedItem {
+    // Extra copies roll again instead of adding chances, so stacking approaches but never passes certainty
+    pub fn chance(&self) -> f32 {
+        1.0 - (1.0 - self.item.chance.clamp(0.0, 1.0)).powi(self.stacks as i32)
+    }
+}
+
+// One effect that went off, already scaled by stacks and synergies
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Proc {
+    pub effect: ItemEffect,
+    pub amount: f32,
+    pub radius: f32
+}
+
+pub struct Inventory {
+    pub items: Vec<OwnedItem>
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self { items: Vec::new() }
+    }
+    pub fn add(&mut self, item: &ItemDef, stacks: u32) {
+        match self.items.iter_mut().find(|owned| owned.item.name == item.name) {
+            Some(owned) => owned.stacks += stacks,
+            None => self.items.push(OwnedItem { item: item.clone(), stacks, counter: 0 })
+        }
+    }
+    pub fn has(&self, name: &str) -> bool {
+        self.items.iter().any(|owned| owned.item.name == name)
+    }
+    pub fn active_synergies<'a>(&self, synergies: &'a [Synergy]) -> impl Iterator<Item = &'a Synergy> {
+        synergies.iter().filter(|synergy| synergy.items.iter().all(|name| self.has(name)))
+    }
+    // Amounts grow linearly with stacks and multiply with every active synergy the item is part of
+    pub fn amount(&self, owned: &OwnedItem, synergies: &[Synergy]) -> f32 {
+        self.active_synergies(synergies)
+            .filter(|synergy| synergy.items.contains(&owned.item.name))
+            .fold(owned.item.amount * owned.stacks as f32, |amount, synergy| amount * synergy.bonus)
+    }
+
+    // Counters advance on every trigger so "every Nth" stays exact, then the chance is rolled;
+    // procs come back sorted by priority with ties kept in pickup order
+    pub fn fire(&mut self, trigger: Trigger, synergies: &[Synergy], rng: &mut StdRng) -> Vec<Proc> {
+        let mut procs = Vec::new();
+        for index in 0..self.items.len() {
+            let owned = &mut self.items[index];
+            if owned.item.trigger != trigger { continue }
+            owned.counter += 1;
+            if owned.counter < owned.item.every { continue }
+            owned.counter = 0;
+            if rng.random::<f32>() >= owned.chance() { continue }
+            let owned = &self.items[index];
+            procs.push((owned.item.priority, Proc { effect: owned.item.effect, amount: self.amount(owned, synergies), radius: owned.item.radius }));
+        }
+        procs.sort_by_key(|(priority, _)| *priority);
+        procs.into_iter().map(|(_, proc)| proc).collect()
+    }
+}
+
+// Which trigger an event stands for; item damage never counts as a hit so chains can't feed themselves.
+// Shots are not here, they go through `shot_handler` while the bullet is still at hand
+fn trigger_of(event: &GameEvent) -> Option<Trigger> {
+    match *event {
+        GameEvent::EnemyKilled { .. } => Some(Trigger::Kill),
+        GameEvent::EnemyDamaged { source: DamageSource::Gun | DamageSource::Sword, .. } => Some(Trigger::Hit),
+        GameEvent::PlayerHit { absorbed: false, .. } => Some(Trigger::Hurt),
+        _ => None
+    }
+}
+
+fn item_damage(amount: f32) -> DamageEvent {
+    DamageEvent::new(amount, DamageType::Physical, DamageSource::Item)
+}
+
+pub fn item_handler(level: &mut Level, player: &mut Player, event: &GameEvent) {
+    let Some(trigger) = trigger_of(event) else { return };
+    let procs = player.items.fire(trigger, &level.item_book.synergies, &mut level.rng);
+    apply(level, player, procs, event, None);
+}
+
+// Called by the gun for each bullet before it joins the others
+pub fn shot_handler(level: &mut Level, player: &mut Player, bullet: &mut Bullet) {
+    let procs = player.items.fire(Trigger::Shot, &level.item_book.synergies, &mut level.rng);
+    let event = GameEvent::WeaponFired { weapon: WeaponKind::Gun, position: bullet.fields.position };
+    apply(level, player, procs, &event, Some(bullet));
+}
+
+fn apply(level: &mut Level, player: &mut Player, procs: Vec<Proc>, event: &GameEvent, mut bullet: Option<&mut Bullet>) {
+    let events: &mut EventBus = &mut level.events;
+    for proc in procs {
+        match proc.effect {
+            ItemEffect::Heal => player.heal(proc.amount),
+            // Jumps from the enemy that was hit to the closest other one in range
+            ItemEffect::Chain => if let GameEvent::EnemyDamaged { id, position, .. } = *event {
+                let target = level.enemies.iter_mut()
+                    .filter(|enemy| enemy.id != id && !enemy.damage.is_dead() && enemy.fields.position.distance_to(position) < proc.radius)
+                    .min_by(|a, b| a.fields.position.distance_to(position).total_cmp(&b.fields.position.distance_to(position)));
+                if let Some(enemy) = target { enemy.hurt(&item_damage(proc.amount), events) }
+            },
+            ItemEffect::Nova => for enemy in &mut level.enemies {
+                if enemy.fields.position.distance_to(player.fields.position) < proc.radius { enemy.hurt(&item_damage(proc.amount), events) }
+            },
+            ItemEffect::Pierce => if let Some(bullet) = bullet.as_deref_mut() {
+                bullet.pierce = bullet.pierce.saturating_add(proc.amount.round() as u8);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    const BOOK: &str = "
+item Late trigger=hit effect=nova priority=5
+item Early trigger=hit effect=heal priority=-1 amount=2
+item Third trigger=kill effect=chain every=3
+item Coin trigger=hit effect=pierce chance=0.5
+synergy Pair items=Late,Early bonus=2
+";
+
+    fn book() -> ItemBook {
+        ItemBook::parse(BOOK).unwrap()
+    }
+    fn item<'a>(book: &'a ItemBook, name: &str) -> &'a ItemDef {
+        book.items.iter().find(|item| item.name == name).unwrap()
+    }
+
+    #[test]
+    fn procs_come_back_in_priority_order() {
+        let book = book();
+        let mut inventory = Inventory::new();
+        inventory.add(item(&book, "Late"), 1);
+        inventory.add(item(&book, "Early"), 1);
+        let procs = inventory.fire(Trigger::Hit, &[], &mut StdRng::seed_from_u64(0));
+        assert_eq!(procs.iter().map(|proc| proc.effect).collect::<Vec<_>>(), vec![ItemEffect::Heal, ItemEffect::Nova]);
+        assert!(inventory.fire(Trigger::Hurt, &[], &mut StdRng::seed_from_u64(0)).is_empty());
+    }
+
+    #[test]
+    fn every_nth_trigger_fires() {
+        let book = book();
+        let mut inventory = Inventory::new();
+        inventory.add(item(&book, "Third"), 1);
+        let mut rng = StdRng::seed_from_u64(0);
+        let fired: Vec<bool> = (0..9).map(|_| !inventory.fire(Trigger::Kill, &[], &mut rng).is_empty()).collect();
+        assert_eq!(fired, [false, false, true, false, false, true, false, false, true]);
+    }
+
+    #[test]
+    fn chance_rolls_follow_the_seed() {
+        let book = book();
+        let mut inventory = Inventory::new();
+        inventory.add(item(&book, "Coin"), 1);
+        let mut rng = StdRng::seed_from_u64(43);
+        let hits = (0..1000).filter(|_| !inventory.fire(Trigger::Hit, &[], &mut rng).is_empty()).count();
+        assert!((400..600).contains(&hits), "{hits} of 1000 at 50%");
+        // The same seed gives the same rolls
+        let rolls = |seed| {
+            let mut inventory = Inventory::new();
+            inventory.add(item(&book, "Coin"), 1);
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..20).map(|_| inventory.fire(Trigger::Hit, &[], &mut rng).len()).collect::<Vec<_>>()
+        };
+        assert_eq!(rolls(7), rolls(7));
+    }
+
+    #[test]
+    fn stacks_add_to_amount_and_chance() {
+        let book = book();
+        let mut inventory = Inventory::new();
+        inventory.add(item(&book, "Early"), 1);
+        inventory.add(item(&book, "Early"), 2);
+        inventory.add(item(&book, "Coin"), 2);
+        assert_eq!(inventory.items.len(), 2);
+        assert_eq!(inventory.amount(&inventory.items[0], &[]), 6.0);
+        assert_eq!(inventory.items[1].chance(), 0.75);
+    }
+
+    #[test]
+    fn synergies_multiply_their_members() {
+        let book = book();
+        let mut inventory = Inventory::new();
+        inventory.add(item(&book, "Early"), 1);
+        inventory.add(item(&book, "Coin"), 1);
+        assert_eq!(inventory.active_synergies(&book.synergies).count(), 0);
+        assert_eq!(inventory.amount(&inventory.items[0], &book.synergies), 2.0);
+        inventory.add(item(&book, "Late"), 1);
+        assert_eq!(inventory.active_synergies(&book.synergies).count(), 1);
+        assert_eq!(inventory.amount(&inventory.items[0], &book.synergies), 4.0);
+        // Coin is not part of the pair
+        assert_eq!(inventory.amount(&inventory.items[1], &book.synergies), 1.0);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::data::{self, Loader};
+use crate::entities::EnemyKind;
+use rand::{Rng, rngs::StdRng};
+
+const LOOT_FILE: &str = "assets/loot.txt";
+const DEFAULT_LOOT: &str = include_str!("../assets/loot.txt");
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LootKind {
+    Heart,
+    Shield,
+    Frenzy,
+    Bomb
+}
+
+#[derive(Copy, Clone)]
+pub struct LootDrop {
+    pub kind: LootKind,
+    pub weight: u32,
+    pub amount: u8,
+    pub duration: f32,
+    pub despawn: f32
+}
+
+pub struct LootTable {
+    // Indexed by EnemyKind: grunt, elite, boss
+    pub chances: [f32; 3],
+    pub drops: Vec<LootDrop>
+}
+
+fn kind_index(kind: EnemyKind) -> usize {
+    match kind {
+        EnemyKind::Grunt => 0,
+        EnemyKind::Elite => 1,
+        EnemyKind::Boss => 2
+    }
+}
+
+impl LootTable {
+    pub fn load(loader: &mut Loader) -> Self {
+        loader.load(LOOT_FILE, DEFAULT_LOOT, Self::parse)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut table = Self { chances: [0.0; 3], drops: Vec::new() };
+        for mut line in data::lines(source) {
+            let keyword = line.keyword;
+            let name = if keyword == "drop" { Some(line.name()?) } else { None };
+            let fields = line.fields()?;
+            match keyword {
+                "chance" => {
+                    let kind = match fields.field("kind").ok_or(fields.error("chance needs a kind"))? {
+                        "grunt" => EnemyKind::Grunt,
+                        "elite" => EnemyKind::Elite,
+                        "boss" => EnemyKind::Boss,
+                        other => return Err(fields.error(format!("unknown enemy kind `{other}`")))
+                    };
+                    table.chances[kind_index(kind)] = fields.number_field("value", 0.0)?.clamp(0.0, 1.0);
+                }
+                "drop" => {
+                    let kind = match name.unwrap() {
+                        "heart" => LootKind::Heart,
+                        "shield" => LootKind::Shield,
+                        "frenzy" => LootKind::Frenzy,
+                        "bomb" => LootKind::Bomb,
+                        other => return Err(fields.error(format!("unknown drop `{other}`")))
+                    };
+                    table.drops.push(LootDrop {
+                        kind,
+                        weight: fields.number_field("weight", 1.0)? as u32,
+                        amount: fields.number_field("amount", 1.0)? as u8,
+                        duration: fields.number_field("duration", 0.0)?,
+                        despawn: fields.number_field("despawn", 0.0)?
+                    });
+                }
+                _ => return Err(fields.error(format!("unknown entry `{keyword}`")))
+            }
+        }
+        Ok(table)
+    }
+
+    // Rolls the enemy's drop chance, then picks a drop by weight
+    pub fn roll(&self, kind: EnemyKind, rng: &mut StdRng) -> Option<LootDrop> {
+        let total: u32 = self.drops.iter().map(|drop| drop.weight).sum();
+        if total == 0 || rng.random::<f32>() >= self.chances[kind_index(kind)] { return None }
+        let mut roll = rng.random_range(0..total);
+        self.drops.iter().find(|drop| if roll < drop.weight { true } else { roll -= drop.weight; false }).copied()
+    }
+}
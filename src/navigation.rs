@@ -0,0 +1,438 @@
+use raylib::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use crate::{WIDTH, HEIGHT};
+
+pub const CELL_SIZE: f32 = HEIGHT/30.0;
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+pub type Cell = (usize, usize);
+
+#[derive(Clone)]
+pub struct NavGrid {
+    pub cols: usize,
+    pub rows: usize,
+    pub cell_size: f32,
+    pub blocked: Vec<bool>
+}
+
+impl NavGrid {
+    pub fn new(cols: usize, rows: usize, cell_size: f32) -> Self {
+        Self { cols, rows, cell_size, blocked: vec![false; cols * rows] }
+    }
+    pub fn from_obstacles(obstacles: &[Rectangle], clearance: f32) -> Self {
+        let mut grid = Self::new((WIDTH / CELL_SIZE).ceil() as usize, (HEIGHT / CELL_SIZE).ceil() as usize, CELL_SIZE);
+        for row in 0..grid.rows {
+            for col in 0..grid.cols {
+                let center = grid.cell_center((col, row));
+                let index = grid.index((col, row));
+                grid.blocked[index] = obstacles.iter().any(|obstacle| {
+                    center.x > obstacle.x - clearance && center.x < obstacle.x + obstacle.width + clearance &&
+                    center.y > obstacle.y - clearance && center.y < obstacle.y + obstacle.height + clearance
+                });
+            }
+        }
+        grid
+    }
+    pub fn index(&self, cell: Cell) -> usize {
+        cell.1 * self.cols + cell.0
+    }
+    pub fn cell_at(&self, position: Vector2) -> Option<Cell> {
+        if position.x < 0.0 || position.y < 0.0 { return None }
+        let (col, row) = ((position.x / self.cell_size) as usize, (position.y / self.cell_size) as usize);
+        if col < self.cols && row < self.rows { Some((col, row)) } else { None }
+    }
+    pub fn cell_center(&self, cell: Cell) -> Vector2 {
+        Vector2 { x: (cell.0 as f32 + 0.5) * self.cell_size, y: (cell.1 as f32 + 0.5) * self.cell_size }
+    }
+    pub fn is_blocked(&self, cell: Cell) -> bool {
+        self.blocked[self.index(cell)]
+    }
+    pub fn nearest_open(&self, cell: Cell, max_radius: usize) -> Option<Cell> {
+        (0..=max_radius as i32).find_map(|radius| {
+            let mut ring = Vec::new();
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    if dx.abs() != radius && dy.abs() != radius { continue }
+                    let (col, row) = (cell.0 as i32 + dx, cell.1 as i32 + dy);
+                    if col < 0 || row < 0 || col >= self.cols as i32 || row >= self.rows as i32 { continue }
+                    if !self.is_blocked((col as usize, row as usize)) { ring.push(((col as usize, row as usize), dx * dx + dy * dy)) }
+                }
+            }
+            ring.into_iter().min_by_key(|(_, distance)| *distance).map(|(open, _)| open)
+        })
+    }
+    // Centre of the open cell closest to a position that may be off the grid or inside an obstacle
+    pub fn nearest_open_point(&self, position: Vector2, max_radius: usize) -> Option<Vector2> {
+        let col = (position.x / self.cell_size).clamp(0.0, (self.cols - 1) as f32) as usize;
+        let row = (position.y / self.cell_size).clamp(0.0, (self.rows - 1) as f32) as usize;
+        self.nearest_open((col, row), max_radius).map(|cell| self.cell_center(cell))
+    }
+    pub fn line_of_sight(&self, from: Vector2, to: Vector2) -> bool {
+        let steps = (from.distance_to(to) / (self.cell_size / 4.0)).ceil().max(1.0) as usize;
+        (0..=steps).all(|step| match self.cell_at(from.lerp(to, step as f32 / steps as f32)) {
+            Some(cell) => !self.is_blocked(cell),
+            None => true
+        })
+    }
+    // Closest open cell around `from` that the threat cannot see
+    pub fn cover_point(&self, from: Vector2, threat: Vector2, search_radius: usize) -> Option<Vector2> {
+        let origin = self.cell_at(from)?;
+        let mut best: Option<(Vector2, f32)> = None;
+        for row in origin.1.saturating_sub(search_radius)..(origin.1 + search_radius + 1).min(self.rows) {
+            for col in origin.0.saturating_sub(search_radius)..(origin.0 + search_radius + 1).min(self.cols) {
+                if self.is_blocked((col, row)) { continue }
+                let center = self.cell_center((col, row));
+                if self.line_of_sight(center, threat) { continue }
+                let distance = center.distance_to(from);
+                if best.is_none_or(|(_, best_distance)| distance < best_distance) { best = Some((center, distance)) }
+            }
+        }
+        best.map(|(center, _)| center)
+    }
+    // An open spot `distance` past `target` on the far side from `from` that still sees it and that `from` can reach,
+    // preferring the route that stays out of the target's sight the longest, then the shorter one
+    pub fn flank_point(&self, from: Vector2, target: Vector2, distance: f32) -> Option<Vector2> {
+        let start = self.nearest_open(self.cell_at(from)?, 1)?;
+        let approach = target - from;
+        let ahead = if approach.length_sqr() > 0.0 { approach.normalized() } else { Vector2 { x: 1.0, y: 0.0 } };
+        let quarter = std::f32::consts::FRAC_PI_4;
+        [0.0, quarter, -quarter].iter()
+            .filter_map(|angle| {
+                let goal = self.cell_at(target + ahead.rotated(*angle).scale_by(distance)).filter(|cell| !self.is_blocked(*cell))?;
+                if !self.line_of_sight(self.cell_center(goal), target) { return None }
+                let path = find_path(self, start, goal)?;
+                let exposed = path.iter().filter(|cell| self.line_of_sight(self.cell_center(**cell), target)).count();
+                Some((exposed, path.len(), goal))
+            })
+            .min_by_key(|(exposed, length, _)| (*exposed, *length))
+            .map(|(_, _, goal)| self.cell_center(goal))
+    }
+    // 8-way neighbours; diagonals are skipped when they would cut a blocked corner
+    pub fn neighbours(&self, cell: Cell) -> Vec<(Cell, u32)> {
+        let mut neighbours = Vec::with_capacity(8);
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (1, -1), (-1, 1), (1, 1)] {
+            let (col, row) = (cell.0 as i32 + dx, cell.1 as i32 + dy);
+            if col < 0 || row < 0 || col >= self.cols as i32 || row >= self.rows as i32 { continue }
+            let next = (col as usize, row as usize);
+            if self.is_blocked(next) { continue }
+            if dx != 0 && dy != 0 {
+                if self.is_blocked((col as usize, cell.1)) || self.is_blocked((cell.0, row as usize)) { continue }
+                neighbours.push((next, DIAGONAL_COST));
+            } else {
+                neighbours.push((next, STRAIGHT_COST));
+            }
+        }
+        neighbours
+    }
+}
+
+pub struct FlowField {
+    pub target: Option<Cell>,
+    pub cost: Vec<u32>,
+    pub next: Vec<Option<Cell>>
+}
+
+impl FlowField {
+    pub fn new() -> Self {
+        Self { target: None, cost: Vec::new(), next: Vec::new() }
+    }
+    pub fn rebuild(&mut self, grid: &NavGrid, target: Vector2) {
+        self.cost = vec![u32::MAX; grid.cols * grid.rows];
+        self.next = vec![None; grid.cols * grid.rows];
+        self.target = grid.cell_at(target);
+        let Some(target) = self.target else { return };
+
+        // Dijkstra outwards from the target so every reachable cell knows its distance
+        let mut open = BinaryHeap::new();
+        self.cost[grid.index(target)] = 0;
+        open.push(Reverse((0, target)));
+        while let Some(Reverse((cost, cell))) = open.pop() {
+            if cost > self.cost[grid.index(cell)] { continue }
+            for (neighbour, step) in grid.neighbours(cell) {
+                let index = grid.index(neighbour);
+                if cost + step < self.cost[index] {
+                    self.cost[index] = cost + step;
+                    open.push(Reverse((cost + step, neighbour)));
+                }
+            }
+        }
+
+        // Each cell then points at its cheapest neighbour
+        for row in 0..grid.rows {
+            for col in 0..grid.cols {
+                let index = grid.index((col, row));
+                if self.cost[index] == u32::MAX || (col, row) == target { continue }
+                self.next[index] = grid.neighbours((col, row))
+                    .into_iter()
+                    .filter(|(neighbour, _)| self.cost[grid.index(*neighbour)] < self.cost[index])
+                    .min_by_key(|(neighbour, step)| self.cost[grid.index(*neighbour)] + step)
+                    .map(|(neighbour, _)| neighbour);
+            }
+        }
+    }
+    // None when outside the grid, unreachable or already in the target cell
+    pub fn direction_at(&self, grid: &NavGrid, position: Vector2) -> Option<Vector2> {
+        let cell = grid.cell_at(position)?;
+        let next = (*self.next.get(grid.index(cell))?)?;
+        Some((grid.cell_center(next) - position).normalized())
+    }
+}
+
+pub fn find_path(grid: &NavGrid, start: Cell, goal: Cell) -> Option<Vec<Cell>> {
+    if grid.is_blocked(start) || grid.is_blocked(goal) { return None }
+    let heuristic = |cell: Cell| {
+        let (dx, dy) = (cell.0.abs_diff(goal.0) as u32, cell.1.abs_diff(goal.1) as u32);
+        STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+    };
+    let mut cost = vec![u32::MAX; grid.cols * grid.rows];
+    let mut came_from: Vec<Option<Cell>> = vec![None; grid.cols * grid.rows];
+    let mut open = BinaryHeap::new();
+    cost[grid.index(start)] = 0;
+    open.push(Reverse((heuristic(start), start)));
+
+    while let Some(Reverse((_, cell))) = open.pop() {
+        if cell == goal {
+            let mut path = vec![goal];
+            while let Some(previous) = came_from[grid.index(*path.last().unwrap())] { path.push(previous) }
+            path.reverse();
+            return Some(path)
+        }
+        let current = cost[grid.index(cell)];
+        for (neighbour, step) in grid.neighbours(cell) {
+            let index = grid.index(neighbour);
+            if current + step < cost[index] {
+                cost[index] = current + step;
+                came_from[index] = Some(cell);
+                open.push(Reverse((current + step + heuristic(neighbour), neighbour)));
+            }
+        }
+    }
+    None
+}
+
+// Drops every waypoint that the previous kept one can already see past
+pub fn smooth_path(grid: &NavGrid, path: &[Vector2]) -> Vec<Vector2> {
+    if path.len() <= 2 { return path.to_vec() }
+    let mut smoothed = vec![path[0]];
+    let mut anchor = 0;
+    while anchor < path.len() - 1 {
+        let furthest = (anchor + 1..path.len())
+            .rev()
+            .find(|&next| grid.line_of_sight(path[anchor], path[next]))
+            .unwrap_or(anchor + 1);
+        smoothed.push(path[furthest]);
+        anchor = furthest;
+    }
+    smoothed
+}
+
+pub struct PathRequest {
+    pub requester: u64,
+    pub from: Vector2,
+    pub to: Vector2
+}
+
+pub struct PathService {
+    pub queue: VecDeque<PathRequest>,
+    pub results: HashMap<u64, Option<Vec<Vector2>>>,
+    pub budget: usize
+}
+
+impl PathService {
+    pub fn new(budget: usize) -> Self {
+        Self { queue: VecDeque::new(), results: HashMap::new(), budget }
+    }
+    pub fn request(&mut self, requester: u64, from: Vector2, to: Vector2) {
+        self.cancel(requester);
+        self.queue.push_back(PathRequest { requester, from, to });
+    }
+    pub fn is_pending(&self, requester: u64) -> bool {
+        self.queue.iter().any(|request| request.requester == requester)
+    }
+    pub fn cancel(&mut self, requester: u64) {
+        self.queue.retain(|request| request.requester != requester);
+        self.results.remove(&requester);
+    }
+    pub fn take(&mut self, requester: u64) -> Option<Option<Vec<Vector2>>> {
+        self.results.remove(&requester)
+    }
+    // Resolves at most `budget` queued requests, the rest wait for later frames
+    pub fn process(&mut self, grid: &NavGrid) {
+        for _ in 0..self.budget {
+            let Some(request) = self.queue.pop_front() else { break };
+            let path = grid.cell_at(request.from)
+                .zip(grid.cell_at(request.to).and_then(|goal| grid.nearest_open(goal, 3)))
+                .and_then(|(start, goal)| find_path(grid, grid.nearest_open(start, 1)?, goal))
+                .map(|cells| {
+                    let mut points: Vec<Vector2> = cells.into_iter().map(|cell| grid.cell_center(cell)).collect();
+                    points[0] = request.from;
+                    if let Some(last) = points.last_mut() && grid.line_of_sight(*last, request.to) { *last = request.to }
+                    smooth_path(grid, &points)
+                });
+            self.results.insert(request.requester, path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a grid from rows of text, `#` being blocked, with unit sized cells
+    fn grid(rows: &[&str]) -> NavGrid {
+        let mut grid = NavGrid::new(rows[0].len(), rows.len(), 1.0);
+        for (row, line) in rows.iter().enumerate() {
+            for (col, tile) in line.chars().enumerate() {
+                let index = grid.index((col, row));
+                grid.blocked[index] = tile == '#';
+            }
+        }
+        grid
+    }
+
+    fn center(grid: &NavGrid, cell: Cell) -> Vector2 {
+        grid.cell_center(cell)
+    }
+
+    #[test]
+    fn detours_around_a_wall() {
+        let grid = grid(&[
+            ".....",
+            ".###.",
+            ".....",
+        ]);
+        let path = find_path(&grid, (2, 0), (2, 2)).unwrap();
+        assert_eq!(path.first(), Some(&(2, 0)));
+        assert_eq!(path.last(), Some(&(2, 2)));
+        assert!(path.iter().all(|&cell| !grid.is_blocked(cell)));
+        // Around either end of the wall
+        assert!(path.iter().any(|&(col, _)| col == 0 || col == 4));
+    }
+
+    #[test]
+    fn blocked_or_unreachable_goal_has_no_path() {
+        let walled = grid(&[
+            "..#..",
+            "..#..",
+            "..#..",
+        ]);
+        assert_eq!(find_path(&walled, (0, 0), (4, 2)), None);
+        let open = grid(&[
+            "...",
+            ".#.",
+            "...",
+        ]);
+        assert_eq!(find_path(&open, (0, 0), (1, 1)), None);
+        assert_eq!(find_path(&open, (1, 1), (0, 0)), None);
+    }
+
+    #[test]
+    fn never_cuts_a_blocked_corner() {
+        let grid = grid(&[
+            ".#",
+            "..",
+        ]);
+        assert!(grid.neighbours((0, 0)).iter().all(|&(cell, _)| cell != (1, 1)));
+        let path = find_path(&grid, (0, 0), (1, 1)).unwrap();
+        assert_eq!(path, vec![(0, 0), (0, 1), (1, 1)]);
+        for pair in path.windows(2) {
+            assert!(pair[0].0 == pair[1].0 || pair[0].1 == pair[1].1, "diagonal step {:?}", pair);
+        }
+    }
+
+    #[test]
+    fn smoothing_drops_waypoints_only_with_line_of_sight() {
+        let open = grid(&[
+            ".....",
+            ".....",
+        ]);
+        let straight: Vec<Vector2> = (0..5).map(|col| center(&open, (col, 0))).collect();
+        assert_eq!(smooth_path(&open, &straight), vec![straight[0], straight[4]]);
+
+        let walled = grid(&[
+            ".....",
+            "..#..",
+            ".....",
+        ]);
+        let around = vec![center(&walled, (0, 1)), center(&walled, (1, 0)), center(&walled, (3, 0)), center(&walled, (4, 1))];
+        let smoothed = smooth_path(&walled, &around);
+        assert_eq!(smoothed.first(), Some(&around[0]));
+        assert_eq!(smoothed.last(), Some(&around[3]));
+        assert!(smoothed.len() > 2);
+        for pair in smoothed.windows(2) { assert!(walled.line_of_sight(pair[0], pair[1])) }
+    }
+
+    #[test]
+    fn finds_open_ground_from_off_the_grid_and_inside_walls() {
+        let grid = grid(&[
+            "##...",
+            "##...",
+            ".....",
+        ]);
+        assert_eq!(grid.nearest_open_point(Vector2 { x: -3.0, y: 0.5 }, 3), Some(center(&grid, (2, 0))));
+        assert_eq!(grid.nearest_open_point(center(&grid, (0, 1)), 3), Some(center(&grid, (0, 2))));
+        assert_eq!(grid.nearest_open_point(Vector2 { x: 9.0, y: 9.0 }, 3), Some(center(&grid, (4, 2))));
+        assert_eq!(grid.nearest_open_point(center(&grid, (0, 0)), 1), None);
+    }
+
+    #[test]
+    fn flanks_to_the_far_side_of_the_target() {
+        let open = grid(&[
+            "...........",
+            "..#........",
+            "..#........",
+            "..#........",
+            "...........",
+        ]);
+        let target = center(&open, (5, 2));
+        for from in [center(&open, (0, 2)), center(&open, (10, 2)), center(&open, (5, 0))] {
+            let goal = open.flank_point(from, target, 3.0).unwrap();
+            assert!((goal - target).dot(from - target) < 0.0, "{goal:?} is on the same side as {from:?}");
+            assert!(open.line_of_sight(goal, target));
+        }
+        // Straight behind is walled off, so it swings round to a diagonal
+        let walled = grid(&[
+            "...........",
+            "...........",
+            "........#..",
+            "...........",
+            "...........",
+        ]);
+        let goal = walled.flank_point(center(&walled, (0, 2)), center(&walled, (5, 2)), 3.0).unwrap();
+        assert!(goal.x > 5.5 && goal.y != 2.5);
+        let boxed = grid(&[
+            "...#.......",
+            "...#.......",
+            "...#.......",
+            "...#.......",
+            "...#.......",
+        ]);
+        assert_eq!(boxed.flank_point(center(&boxed, (0, 2)), center(&boxed, (5, 2)), 3.0), None);
+    }
+
+    #[test]
+    fn process_respects_budget_and_cancel() {
+        let grid = grid(&[
+            ".....",
+            ".....",
+            ".....",
+        ]);
+        let mut service = PathService::new(2);
+        for requester in 1..=4 { service.request(requester, center(&grid, (0, 0)), center(&grid, (4, 2))) }
+        service.cancel(3);
+        assert!(!service.is_pending(3));
+
+        service.process(&grid);
+        assert_eq!(service.results.len(), 2);
+        assert!(service.take(1).unwrap().is_some());
+        assert!(service.take(2).unwrap().is_some());
+        assert!(service.is_pending(4));
+
+        service.process(&grid);
+        assert!(service.take(3).is_none());
+        assert!(service.take(4).unwrap().is_some());
+        assert!(service.queue.is_empty());
+    }
+}
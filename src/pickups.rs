@@ -0,0 +1,158 @@
+use crate::basic::BallEnt;
+use crate::entities::{Level, Player};
+use crate::stats::Stat;
+use crate::loot::{LootDrop, LootKind};
+use crate::spawning::{VIEW_HALF_WIDTH, VIEW_HALF_HEIGHT};
+use crate::utils::Cooldown;
+use crate::damage::{DamageEvent, DamageSource, DamageType};
+use crate::events::GameEvent;
+use crate::draft::Draft;
+use crate::HEIGHT;
+use raylib::{color::Color, prelude::*};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PickupKind {
+    Experience(u32),
+    Heart(u8),
+    Shield,
+    Frenzy(f32),
+    Bomb
+}
+
+#[derive(Copy, Clone)]
+pub struct Pickup {
+    pub fields: BallEnt,
+    pub kind: PickupKind,
+    pub magnetized: bool,
+    pub lifetime: Option<Cooldown>
+}
+
+impl Pickup {
+    pub fn experience(position: Vector2, value: u32) -> Self {
+        let (radius, color) = match value {
+            0..=2 => (HEIGHT/150.0, Color::SKYBLUE),
+            3..=9 => (HEIGHT/120.0, Color::LIME),
+            _ => (HEIGHT/90.0, Color::MAGENTA)
+        };
+        Self {
+            fields: BallEnt {
+                position,
+                direction: Vector2::new(0.0, 0.0),
+                speed: 0.0,
+                radius,
+                color
+            },
+            kind: PickupKind::Experience(value),
+            magnetized: false,
+            lifetime: None
+        }
+    }
+    pub fn loot(position: Vector2, drop: LootDrop) -> Self {
+        let (kind, color) = match drop.kind {
+            LootKind::Heart => (PickupKind::Heart(drop.amount), Color::RED),
+            LootKind::Shield => (PickupKind::Shield, Color::BLUE),
+            LootKind::Frenzy => (PickupKind::Frenzy(drop.duration), Color::ORANGE),
+            LootKind::Bomb => (PickupKind::Bomb, Color::BLACK)
+        };
+        Self {
+            fields: BallEnt {
+                position,
+                direction: Vector2::new(0.0, 0.0),
+                speed: 0.0,
+                radius: HEIGHT/60.0,
+                color
+            },
+            kind,
+            magnetized: false,
+            lifetime: (drop.despawn > 0.0).then_some(Cooldown {
+                cooldown: drop.despawn * 10.0,
+                cooldown_value: drop.despawn * 10.0
+            })
+        }
+    }
+    // Blinks through the last few seconds before it despawns
+    pub fn visible(&self, time: f32) -> bool {
+        self.lifetime.is_none_or(|lifetime| lifetime.cooldown_value > 30.0 || ((time * 8.0) as u32).is_multiple_of(2))
+    }
+}
+
+// Experience needed to go from `level` to the next one
+pub fn experience_to_next(level: u128) -> u32 {
+    let level = level.min(1000) as u32;
+    10 + 6 * level + level * level / 2
+}
+
+pub fn pickup_handler(rl: &RaylibHandle, level: &mut Level, player: &mut Player) {
+    let magnet_radius = player.stats.get(Stat::PickupRadius);
+    let pull = HEIGHT/(720.0/900.0);
+    let max_speed = HEIGHT/(720.0/600.0);
+
+    level.pickups.retain_mut(|pickup| {
+        let offset = player.fields.position - pickup.fields.position;
+        if let Some(lifetime) = &mut pickup.lifetime {
+            lifetime.cooldown_value = (lifetime.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
+            if lifetime.cooldown_value <= 0.0 { return false }
+        }
+        // Once caught by the magnet a gem keeps homing in, even if the player runs off
+        if offset.length() < magnet_radius && matches!(pickup.kind, PickupKind::Experience(_)) { pickup.magnetized = true }
+        if pickup.magnetized {
+            pickup.fields.speed = (pickup.fields.speed + pull * rl.get_frame_time()).min(max_speed);
+            pickup.fields.direction = offset.normalized();
+            pickup.fields.position += pickup.fields.direction.scale_by(pickup.fields.speed * rl.get_frame_time());
+        }
+        if !check_collision_circles(player.fields.position, player.fields.radius, pickup.fields.position, pickup.fields.radius) { return true }
+        level.events.emit(GameEvent::PickupCollected { kind: pickup.kind, position: pickup.fields.position });
+        match pickup.kind {
+            PickupKind::Experience(value) => player.experience += value,
+            PickupKind::Heart(amount) => player.heal(amount as f32),
+            PickupKind::Shield => player.shield = true,
+            PickupKind::Frenzy(duration) => player.frenzy = Cooldown { cooldown: duration * 10.0, cooldown_value: duration * 10.0 },
+            // A lethal hit like any other, so the usual death handling drops their gems and counts the kills
+            PickupKind::Bomb => for enemy in &mut level.enemies {
+                let offset = enemy.fields.position - player.fields.position;
+                if offset.x.abs() < VIEW_HALF_WIDTH && offset.y.abs() < VIEW_HALF_HEIGHT {
+                    let lethal = DamageEvent::new(enemy.damage.hitpoint, DamageType::Physical, DamageSource::Bomb);
+                    enemy.hurt(&lethal, &mut level.events);
+                }
+            }
+        }
+        false
+    });
+    queue_level_ups(player, &mut level.draft);
+}
+
+// Level-ups earned in one go queue up and are offered one after another
+pub fn queue_level_ups(player: &mut Player, draft: &mut Draft) {
+    while player.experience >= experience_to_next(player.level + draft.queued as u128) {
+        player.experience -= experience_to_next(player.level + draft.queued as u128);
+        draft.queued += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_level_needs_more_experience() {
+        assert_eq!(experience_to_next(0), 10);
+        assert_eq!(experience_to_next(1), 16);
+        assert_eq!(experience_to_next(4), 42);
+        assert!((0..50).all(|level| experience_to_next(level + 1) > experience_to_next(level)));
+        // Capped so endless runs never overflow
+        assert_eq!(experience_to_next(5000), experience_to_next(1000));
+    }
+
+    #[test]
+    fn several_level_ups_queue_at_once() {
+        let (mut player, mut draft) = (Player::new(), Draft::new());
+        player.experience = 9;
+        queue_level_ups(&mut player, &mut draft);
+        assert_eq!(draft.queued, 0);
+        // 10 for the first level, 16 for the second, and 5 left over toward the third
+        player.experience = 31;
+        queue_level_ups(&mut player, &mut draft);
+        assert_eq!(draft.queued, 2);
+        assert_eq!(player.experience, 5);
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{WIDTH, HEIGHT, CAMERA_ZOOM};
+use rand::{Rng, rngs::StdRng};
+use raylib::prelude::*;
+use std::f32::consts::{PI, TAU};
+
+// Depth of the band just outside the arena walls that edge spawns are drawn from
+pub const SPAWN_BAND: f32 = HEIGHT/10.0;
+pub const MIN_SPAWN_DISTANCE: f32 = HEIGHT/4.0;
+// Half the camera view around the player plus a margin; spawns inside it would be on screen
+pub const VIEW_HALF_WIDTH: f32 = WIDTH / (2.0 * CAMERA_ZOOM) + HEIGHT/20.0;
+pub const VIEW_HALF_HEIGHT: f32 = HEIGHT / (2.0 * CAMERA_ZOOM) + HEIGHT/20.0;
+const CLUSTER_SPREAD: f32 = HEIGHT/15.0;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SpawnPattern {
+    RandomEdge,
+    Ring,
+    Pincer,
+    Cluster,
+    Corner
+}
+
+// One enemy's place within a group; `anchor` is rolled once per group so its members agree on a side or phase
+#[derive(Copy, Clone)]
+pub struct SpawnSlot {
+    pub index: u32,
+    pub count: u32,
+    pub anchor: f32
+}
+
+impl SpawnPattern {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "edge" => Some(Self::RandomEdge),
+            "ring" => Some(Self::Ring),
+            "pincer" => Some(Self::Pincer),
+            "cluster" => Some(Self::Cluster),
+            "corner" => Some(Self::Corner),
+            _ => None
+        }
+    }
+
+    pub fn position(&self, slot: SpawnSlot, player: Vector2, facing: Vector2, rng: &mut StdRng) -> Vector2 {
+        let position = match self {
+            Self::RandomEdge => edge_point(rng.random(), rng.random()),
+            Self::Ring => {
+                let angle = slot.anchor * TAU + slot.index as f32 / slot.count.max(1) as f32 * TAU;
+                player + Vector2 { x: angle.cos(), y: angle.sin() }.scale_by(Vector2 { x: VIEW_HALF_WIDTH, y: VIEW_HALF_HEIGHT }.length())
+            }
+            Self::Pincer => {
+                // Alternate between the two sides perpendicular to where the player is facing
+                let facing = if facing.length_sqr() > 0.0 { facing.normalized() } else { Vector2 { x: 1.0, y: 0.0 } };
+                let side = if slot.index.is_multiple_of(2) { PI / 2.0 } else { -PI / 2.0 };
+                let spread = (rng.random::<f32>() - 0.5) * PI / 6.0;
+                player + facing.rotated(side + spread).scale_by(VIEW_HALF_HEIGHT + rng.random::<f32>() * SPAWN_BAND)
+            }
+            Self::Cluster => {
+                let angle = rng.random::<f32>() * TAU;
+                edge_point(slot.anchor, 0.5) + Vector2 { x: angle.cos(), y: angle.sin() }.scale_by(rng.random::<f32>().sqrt() * CLUSTER_SPREAD)
+            }
+            Self::Corner => {
+                let corner = Vector2 {
+                    x: if slot.anchor < 0.5 { -SPAWN_BAND / 2.0 } else { WIDTH + SPAWN_BAND / 2.0 },
+                    y: if ((slot.anchor * 4.0) as u32).is_multiple_of(2) { -SPAWN_BAND / 2.0 } else { HEIGHT + SPAWN_BAND / 2.0 }
+                };
+                let angle = rng.random::<f32>() * TAU;
+                corner + Vector2 { x: angle.cos(), y: angle.sin() }.scale_by(rng.random::<f32>().sqrt() * SPAWN_BAND / 2.0)
+            }
+        };
+        settle(position, player)
+    }
+}
+
+// Maps `along` onto the arena perimeter (weighted by side length) and `depth` into the band outside it
+pub fn edge_point(along: f32, depth: f32) -> Vector2 {
+    let perimeter = 2.0 * (WIDTH + HEIGHT);
+    let distance = along.clamp(0.0, 1.0) * perimeter;
+    let depth = depth.clamp(0.0, 1.0) * SPAWN_BAND;
+    if distance < WIDTH {
+        Vector2 { x: distance, y: -depth }
+    } else if distance < WIDTH + HEIGHT {
+        Vector2 { x: WIDTH + depth, y: distance - WIDTH }
+    } else if distance < 2.0 * WIDTH + HEIGHT {
+        Vector2 { x: 2.0 * WIDTH + HEIGHT - distance, y: HEIGHT + depth }
+    } else {
+        Vector2 { x: -depth, y: perimeter - distance }
+    }
+}
+
+// Pushes a spawn straight away from the player until it is at least `min_distance` out
+pub fn keep_distance(position: Vector2, player: Vector2, min_distance: f32) -> Vector2 {
+    let offset = position - player;
+    if offset.length() >= min_distance { return position }
+    let direction = if offset.length_sqr() > 0.0 { offset.normalized() } else { Vector2 { x: 0.0, y: -1.0 } };
+    player + direction.scale_by(min_distance)
+}
+
+// The arena plus the band around it, the only place a spawn may land
+pub fn in_spawn_area(position: Vector2) -> bool {
+    (-SPAWN_BAND..=WIDTH + SPAWN_BAND).contains(&position.x) && (-SPAWN_BAND..=HEIGHT + SPAWN_BAND).contains(&position.y)
+}
+
+fn clamp_to_spawn_area(position: Vector2) -> Vector2 {
+    Vector2 { x: position.x.clamp(-SPAWN_BAND, WIDTH + SPAWN_BAND), y: position.y.clamp(-SPAWN_BAND, HEIGHT + SPAWN_BAND) }
+}
+
+// Keeps a pattern's point inside the spawn area and away from the player, then off screen where there is room
+fn settle(position: Vector2, player: Vector2) -> Vector2 {
+    let mut position = clamp_to_spawn_area(keep_distance(position, player, MIN_SPAWN_DISTANCE));
+    // Clamping can drag it back toward a player near the walls; stepping toward the middle always fits
+    if position.distance_to(player) < MIN_SPAWN_DISTANCE {
+        let middle = Vector2 { x: WIDTH / 2.0, y: HEIGHT / 2.0 } - player;
+        let direction = if middle.length_sqr() > 0.0 { middle.normalized() } else { Vector2 { x: 0.0, y: -1.0 } };
+        position = player + direction.scale_by(MIN_SPAWN_DISTANCE);
+    }
+    keep_off_screen(position, player)
+}
+
+// Slides a spawn out of the camera view by the shortest move that stays in the spawn area, if there is one
+pub fn keep_off_screen(position: Vector2, player: Vector2) -> Vector2 {
+    let offset = position - player;
+    if offset.x.abs() >= VIEW_HALF_WIDTH || offset.y.abs() >= VIEW_HALF_HEIGHT { return position }
+    let sign = |value: f32| if value < 0.0 { -1.0 } else { 1.0 };
+    let candidates = [
+        Vector2 { x: player.x + VIEW_HALF_WIDTH * sign(offset.x), y: position.y },
+        Vector2 { x: player.x - VIEW_HALF_WIDTH * sign(offset.x), y: position.y },
+        Vector2 { x: position.x, y: player.y + VIEW_HALF_HEIGHT * sign(offset.y) },
+        Vector2 { x: position.x, y: player.y - VIEW_HALF_HEIGHT * sign(offset.y) }
+    ];
+    candidates.into_iter()
+        .filter(|candidate| in_spawn_area(*candidate))
+        .min_by(|a, b| a.distance_to(position).total_cmp(&b.distance_to(position)))
+        .unwrap_or(position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    const PATTERNS: [SpawnPattern; 5] = [SpawnPattern::RandomEdge, SpawnPattern::Ring, SpawnPattern::Pincer, SpawnPattern::Cluster, SpawnPattern::Corner];
+    const RIGHT: Vector2 = Vector2 { x: 1.0, y: 0.0 };
+
+    fn middle() -> Vector2 {
+        Vector2 { x: WIDTH / 2.0, y: HEIGHT / 2.0 }
+    }
+
+    // A group of eight from one pattern, sharing an anchor like the director's waves do
+    fn group(pattern: SpawnPattern, player: Vector2, anchor: f32, rng: &mut StdRng) -> Vec<Vector2> {
+        (0..8).map(|index| pattern.position(SpawnSlot { index, count: 8, anchor }, player, RIGHT, rng)).collect()
+    }
+
+    #[test]
+    fn every_pattern_keeps_its_distance_inside_the_spawn_area() {
+        let mut rng = StdRng::seed_from_u64(29);
+        let players = [middle(), Vector2 { x: 10.0, y: 10.0 }, Vector2 { x: WIDTH - 10.0, y: HEIGHT / 2.0 }, Vector2 { x: WIDTH / 3.0, y: HEIGHT - 5.0 }];
+        for pattern in PATTERNS {
+            for player in players {
+                for _ in 0..50 {
+                    let anchor = rng.random();
+                    for position in group(pattern, player, anchor, &mut rng) {
+                        assert!(position.distance_to(player) >= MIN_SPAWN_DISTANCE - 0.01, "{pattern:?} spawned {position:?} too close to {player:?}");
+                        assert!(in_spawn_area(position), "{pattern:?} spawned {position:?} outside the arena");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_edge_lands_in_the_band() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for position in group(SpawnPattern::RandomEdge, middle(), 0.0, &mut rng) {
+            let inside = position.x > 0.0 && position.x < WIDTH && position.y > 0.0 && position.y < HEIGHT;
+            assert!(!inside, "{position:?} is inside the walls");
+        }
+    }
+
+    #[test]
+    fn ring_surrounds_the_player() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let ring = group(SpawnPattern::Ring, middle(), 0.0, &mut rng);
+        let offsets: Vec<Vector2> = ring.iter().map(|position| *position - middle()).collect();
+        assert!(offsets.iter().any(|offset| offset.x > 0.0) && offsets.iter().any(|offset| offset.x < 0.0));
+        assert!(offsets.iter().any(|offset| offset.y > 0.0) && offsets.iter().any(|offset| offset.y < 0.0));
+    }
+
+    #[test]
+    fn pincer_comes_from_opposite_sides() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let pincer = group(SpawnPattern::Pincer, middle(), 0.0, &mut rng);
+        for (index, position) in pincer.iter().enumerate() {
+            let offset = *position - middle();
+            // Perpendicular to the facing, alternating above and below
+            assert!(offset.y.abs() > offset.x.abs(), "{offset:?} is not off to the side");
+            assert_eq!(offset.y > 0.0, index % 2 == 0, "{offset:?} is on the wrong side");
+        }
+    }
+
+    #[test]
+    fn cluster_stays_within_its_spread() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for anchor in [0.1, 0.4, 0.6, 0.9] {
+            let center = edge_point(anchor, 0.5);
+            for position in group(SpawnPattern::Cluster, middle(), anchor, &mut rng) {
+                assert!(position.distance_to(center) <= CLUSTER_SPREAD + 0.01, "{position:?} strayed from {center:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn corner_gathers_at_the_anchored_corner() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let corners = [(0.1, Vector2 { x: 0.0, y: 0.0 }), (0.3, Vector2 { x: 0.0, y: HEIGHT }), (0.6, Vector2 { x: WIDTH, y: 0.0 }), (0.8, Vector2 { x: WIDTH, y: HEIGHT })];
+        for (anchor, corner) in corners {
+            for position in group(SpawnPattern::Corner, middle(), anchor, &mut rng) {
+                assert!(position.distance_to(corner) <= SPAWN_BAND * 1.5, "{position:?} is far from {corner:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GameState {
+    Title,
+    Playing,
+    Paused,
+    LevelUp,
+    GameOver,
+    Results
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum StateEvent {
+    Start,
+    Pause,
+    Resume,
+    LevelUpOffered,
+    LevelUpChosen,
+    PlayerDied,
+    Continue,
+    Restart,
+    ToTitle
+}
+
+impl GameState {
+    // Events that make no sense in the current state leave it unchanged
+    pub fn next(self, event: StateEvent) -> Self {
+        match (self, event) {
+            (Self::Title, StateEvent::Start) => Self::Playing,
+            (Self::Playing, StateEvent::Pause) => Self::Paused,
+            (Self::Playing, StateEvent::LevelUpOffered) => Self::LevelUp,
+            (Self::Playing, StateEvent::PlayerDied) => Self::GameOver,
+            (Self::Paused, StateEvent::Resume) => Self::Playing,
+            (Self::Paused, StateEvent::ToTitle) => Self::Title,
+            (Self::LevelUp, StateEvent::LevelUpChosen) => Self::Playing,
+            (Self::GameOver, StateEvent::Continue) => Self::Results,
+            (Self::Results, StateEvent::Restart) => Self::Playing,
+            (Self::Results, StateEvent::ToTitle) => Self::Title,
+            (state, _) => state
+        }
+    }
+    // Whether taking this event begins a fresh run, so the player and level need resetting
+    pub fn starts_run(self, event: StateEvent) -> bool {
+        matches!((self, event), (Self::Title, StateEvent::Start) | (Self::Results, StateEvent::Restart))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATES: [GameState; 6] = [GameState::Title, GameState::Playing, GameState::Paused, GameState::LevelUp, GameState::GameOver, GameState::Results];
+    const EVENTS: [StateEvent; 9] = [
+        StateEvent::Start, StateEvent::Pause, StateEvent::Resume, StateEvent::LevelUpOffered, StateEvent::LevelUpChosen,
+        StateEvent::PlayerDied, StateEvent::Continue, StateEvent::Restart, StateEvent::ToTitle
+    ];
+    const LEGAL: [(GameState, StateEvent, GameState); 10] = [
+        (GameState::Title, StateEvent::Start, GameState::Playing),
+        (GameState::Playing, StateEvent::Pause, GameState::Paused),
+        (GameState::Playing, StateEvent::LevelUpOffered, GameState::LevelUp),
+        (GameState::Playing, StateEvent::PlayerDied, GameState::GameOver),
+        (GameState::Paused, StateEvent::Resume, GameState::Playing),
+        (GameState::Paused, StateEvent::ToTitle, GameState::Title),
+        (GameState::LevelUp, StateEvent::LevelUpChosen, GameState::Playing),
+        (GameState::GameOver, StateEvent::Continue, GameState::Results),
+        (GameState::Results, StateEvent::Restart, GameState::Playing),
+        (GameState::Results, StateEvent::ToTitle, GameState::Title)
+    ];
+
+    #[test]
+    fn legal_transitions_move_and_everything_else_is_ignored() {
+        for state in STATES {
+            for event in EVENTS {
+                let expected = LEGAL.iter().find(|(from, on, _)| *from == state && *on == event).map_or(state, |(_, _, to)| *to);
+                assert_eq!(state.next(event), expected, "{state:?} on {event:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn level_up_waits_for_a_pick() {
+        for event in EVENTS.into_iter().filter(|event| *event != StateEvent::LevelUpChosen) {
+            assert_eq!(GameState::LevelUp.next(event), GameState::LevelUp, "{event:?}");
+        }
+        assert_eq!(GameState::LevelUp.next(StateEvent::LevelUpChosen), GameState::Playing);
+    }
+
+    #[test]
+    fn only_start_and_restart_begin_a_run() {
+        for state in STATES {
+            for event in EVENTS {
+                let fresh = matches!((state, event), (GameState::Title, StateEvent::Start) | (GameState::Results, StateEvent::Restart));
+                assert_eq!(state.starts_run(event), fresh, "{state:?} on {event:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn restarting_after_game_over_goes_through_the_results() {
+        let mut state = GameState::Title.next(StateEvent::Start).next(StateEvent::PlayerDied);
+        // Straight from game over a restart neither resets nor moves, the results come first
+        assert!(!state.starts_run(StateEvent::Restart));
+        assert_eq!(state.next(StateEvent::Restart), GameState::GameOver);
+        state = state.next(StateEvent::Continue);
+        assert!(state.starts_run(StateEvent::Restart));
+        assert_eq!(state.next(StateEvent::Restart), GameState::Playing);
+    }
+}
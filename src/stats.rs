@@ -0,0 +1,113 @@
+use crate::HEIGHT;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Stat {
+    MoveSpeed,
+    MaxHitpoint,
+    ReloadTime,
+    BulletSpeed,
+    BulletSize,
+    Bounces,
+    Projectiles,
+    Spread,
+    Pierce,
+    SwordReach,
+    SwordDamage,
+    GunDamage,
+    CritChance,
+    CritMultiplier,
+    PickupRadius
+}
+
+impl Stat {
+    pub fn base(&self) -> f32 {
+        match self {
+            Self::MoveSpeed => HEIGHT/(720.0/200.0),
+            Self::MaxHitpoint => 5.0,
+            Self::ReloadTime => 10.0,
+            Self::BulletSpeed => HEIGHT/(720.0/500.0),
+            Self::BulletSize => HEIGHT/144.0,
+            Self::Bounces => 1.0,
+            Self::Projectiles => 1.0,
+            // Degrees between neighbouring projectiles in a fan
+            Self::Spread => 10.0,
+            Self::Pierce => 0.0,
+            Self::SwordReach => HEIGHT/(720.0/80.0),
+            Self::SwordDamage => 0.0,
+            Self::GunDamage => 1.0,
+            Self::CritChance => 0.05,
+            Self::CritMultiplier => 2.0,
+            Self::PickupRadius => HEIGHT/10.0
+        }
+    }
+    // Floors keep stacked penalties from producing nonsense like a negative reload
+    fn min(&self) -> f32 {
+        match self {
+            Self::MaxHitpoint => 1.0,
+            Self::ReloadTime => 1.0,
+            Self::Projectiles => 1.0,
+            _ => 0.0
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Modifier {
+    Add(Stat, f32),
+    Mul(Stat, f32)
+}
+
+// Final value is (base + every Add) * every Mul, so order of acquisition never matters
+pub struct Stats {
+    pub modifiers: Vec<Modifier>
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self { modifiers: Vec::new() }
+    }
+    pub fn push(&mut self, modifier: Modifier) {
+        self.modifiers.push(modifier);
+    }
+    pub fn get(&self, stat: Stat) -> f32 {
+        let (mut add, mut mul) = (0.0, 1.0);
+        for modifier in &self.modifiers {
+            match *modifier {
+                Modifier::Add(s, value) if s == stat => add += value,
+                Modifier::Mul(s, value) if s == stat => mul *= value,
+                _ => {}
+            }
+        }
+        ((stat.base() + add) * mul).max(stat.min())
+    }
+    pub fn get_u8(&self, stat: Stat) -> u8 {
+        self.get(stat).round().min(u8::MAX as f32) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_then_multiplies_in_any_order() {
+        let mut stats = Stats::new();
+        assert_eq!(stats.get(Stat::MaxHitpoint), 5.0);
+        stats.push(Modifier::Mul(Stat::MaxHitpoint, 2.0));
+        stats.push(Modifier::Add(Stat::MaxHitpoint, 1.0));
+        stats.push(Modifier::Mul(Stat::MaxHitpoint, 1.5));
+        stats.push(Modifier::Add(Stat::MaxHitpoint, 2.0));
+        stats.push(Modifier::Add(Stat::Bounces, 4.0));
+        assert_eq!(stats.get(Stat::MaxHitpoint), 24.0);
+        assert_eq!(stats.get_u8(Stat::Bounces), 5);
+    }
+
+    #[test]
+    fn penalties_stop_at_the_floor() {
+        let mut stats = Stats::new();
+        stats.push(Modifier::Mul(Stat::ReloadTime, 0.05));
+        assert_eq!(stats.get(Stat::ReloadTime), 1.0);
+        stats.push(Modifier::Add(Stat::Pierce, -3.0));
+        assert_eq!(stats.get(Stat::Pierce), 0.0);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::utils::Cooldown;
+use crate::damage::{DamageEvent, DamageSource, DamageType};
+use raylib::color::Color;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum StatusKind {
+    Burn,
+    Slow,
+    Freeze,
+    Poison
+}
+
+impl StatusKind {
+    // Seconds an application lasts
+    pub fn duration(&self) -> f32 {
+        match self {
+            Self::Burn => 3.0,
+            Self::Slow => 2.0,
+            Self::Freeze => 1.0,
+            Self::Poison => 4.0
+        }
+    }
+    // Seconds between damage ticks, zero for effects that never deal damage
+    fn tick_interval(&self) -> f32 {
+        match self {
+            Self::Burn => 1.0,
+            Self::Poison => 1.5,
+            Self::Slow | Self::Freeze => 0.0
+        }
+    }
+    // Damage per stack per tick
+    fn tick_damage(&self) -> (f32, DamageType) {
+        match self {
+            Self::Burn => (1.0, DamageType::Fire),
+            Self::Poison => (0.5, DamageType::Poison),
+            Self::Slow | Self::Freeze => (0.0, DamageType::Physical)
+        }
+    }
+    fn max_stacks(&self) -> u8 {
+        match self {
+            Self::Freeze => 1,
+            Self::Burn | Self::Slow => 3,
+            Self::Poison => 5
+        }
+    }
+    pub fn color(&self) -> Color {
+        match self {
+            Self::Burn => Color::ORANGE,
+            Self::Slow => Color::SKYBLUE,
+            Self::Freeze => Color::RAYWHITE,
+            Self::Poison => Color::GREEN
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    pub stacks: u8,
+    pub duration: Cooldown,
+    pub tick: Cooldown
+}
+
+#[derive(Clone)]
+pub struct StatusEffects {
+    pub effects: Vec<StatusEffect>
+}
+
+impl StatusEffects {
+    pub fn new() -> Self {
+        Self { effects: Vec::new() }
+    }
+
+    // Reapplying refreshes the duration and adds a stack up to the kind's cap; the tick timer carries on
+    pub fn apply(&mut self, kind: StatusKind) {
+        let duration = kind.duration() * 10.0;
+        if let Some(effect) = self.effects.iter_mut().find(|effect| effect.kind == kind) {
+            effect.stacks = (effect.stacks + 1).min(kind.max_stacks());
+            effect.duration.cooldown_value = duration;
+            return
+        }
+        self.effects.push(StatusEffect {
+            kind,
+            stacks: 1,
+            duration: Cooldown {
+                cooldown: duration,
+                cooldown_value: duration
+            },
+            tick: Cooldown {
+                cooldown: kind.tick_interval() * 10.0,
+                cooldown_value: kind.tick_interval() * 10.0
+            }
+        });
+    }
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    // Advances every effect by `dt` seconds and returns one damage event per effect that ticked meanwhile
+    pub fn update(&mut self, dt: f32) -> Vec<DamageEvent> {
+        let mut events = Vec::new();
+        for effect in &mut self.effects {
+            let mut ticks = 0;
+            let elapsed = (10.0 * dt).min(effect.duration.cooldown_value);
+            effect.duration.cooldown_value -= 10.0 * dt;
+            if effect.tick.cooldown <= 0.0 { continue }
+            effect.tick.cooldown_value -= elapsed;
+            while effect.tick.cooldown_value <= 0.0 {
+                ticks += 1;
+                effect.tick.cooldown_value += effect.tick.cooldown;
+            }
+            if ticks == 0 { continue }
+            let (amount, kind) = effect.kind.tick_damage();
+            events.push(DamageEvent::new(amount * effect.stacks as f32 * ticks as f32, kind, DamageSource::Status(effect.kind)));
+        }
+        self.effects.retain(|effect| effect.duration.cooldown_value > 0.0);
+        events
+    }
+
+    // Freeze stops movement outright, each slow stack takes off a further quarter
+    pub fn speed_multiplier(&self) -> f32 {
+        if self.has(StatusKind::Freeze) { return 0.0 }
+        self.effects.iter()
+            .filter(|effect| effect.kind == StatusKind::Slow)
+            .map(|effect| 0.75_f32.powi(effect.stacks as i32))
+            .product()
+    }
+
+    // The most recently applied effect decides the tint
+    pub fn tint(&self) -> Option<Color> {
+        self.effects.last().map(|effect| effect.kind.color())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Steps `seconds` in tenths and totals the damage dealt
+    fn run(effects: &mut StatusEffects, seconds: f32) -> f32 {
+        (0..(seconds * 10.0).round() as usize).flat_map(|_| effects.update(0.1)).map(|event| event.amount).sum()
+    }
+
+    #[test]
+    fn ticks_once_per_interval_over_the_duration() {
+        let mut effects = StatusEffects::new();
+        effects.apply(StatusKind::Burn);
+        assert_eq!(run(&mut effects, 5.0), 3.0);
+        let mut effects = StatusEffects::new();
+        effects.apply(StatusKind::Poison);
+        // Ticks at 1.5 and 3 seconds, then wears off at 4 before a third
+        assert_eq!(run(&mut effects, 5.0), 1.0);
+    }
+
+    #[test]
+    fn stacks_scale_damage_up_to_the_cap() {
+        let mut effects = StatusEffects::new();
+        for _ in 0..5 { effects.apply(StatusKind::Burn) }
+        assert_eq!(effects.effects[0].stacks, 3);
+        assert_eq!(run(&mut effects, 1.0), 3.0);
+        let mut effects = StatusEffects::new();
+        for _ in 0..5 { effects.apply(StatusKind::Freeze) }
+        assert_eq!(effects.effects[0].stacks, 1);
+    }
+
+    #[test]
+    fn reapplying_refreshes_the_duration() {
+        let mut effects = StatusEffects::new();
+        effects.apply(StatusKind::Slow);
+        run(&mut effects, 1.5);
+        effects.apply(StatusKind::Slow);
+        run(&mut effects, 1.5);
+        assert!(effects.has(StatusKind::Slow));
+        run(&mut effects, 0.6);
+        assert!(!effects.has(StatusKind::Slow));
+    }
+
+    #[test]
+    fn slow_and_freeze_change_speed() {
+        let mut effects = StatusEffects::new();
+        assert_eq!(effects.speed_multiplier(), 1.0);
+        effects.apply(StatusKind::Slow);
+        assert_eq!(effects.speed_multiplier(), 0.75);
+        effects.apply(StatusKind::Slow);
+        assert_eq!(effects.speed_multiplier(), 0.5625);
+        effects.apply(StatusKind::Freeze);
+        assert_eq!(effects.speed_multiplier(), 0.0);
+    }
+
+    #[test]
+    fn effects_expire() {
+        let mut effects = StatusEffects::new();
+        effects.apply(StatusKind::Freeze);
+        effects.apply(StatusKind::Burn);
+        run(&mut effects, 1.1);
+        assert!(!effects.has(StatusKind::Freeze));
+        assert!(effects.has(StatusKind::Burn));
+        run(&mut effects, 2.0);
+        assert!(effects.effects.is_empty());
+        assert_eq!(effects.speed_multiplier(), 1.0);
+        assert_eq!(effects.tint(), None);
+    }
+}
@@ -1,79 +1,106 @@
-use raylib::{RaylibHandle, ffi::KeyboardKey};
-use crate::entities::{Level, Player};
-
-#[derive(Copy, Clone)]
-pub struct DamageSystem {
-    pub hitpoint: u8,
-    pub damage_cooldown: Cooldown
-}
-
 #[derive(Copy, Clone)]
 pub struct Cooldown {
     pub cooldown: f32,
     pub cooldown_value: f32
 }
 
-pub struct Prompt {
-    pub appear: bool,
-    pub text: (String, String),
-    pub has_chosen: bool
+// Remembers a press for a short window so it can fire as soon as the action becomes possible
+#[derive(Copy, Clone)]
+pub struct BufferedInput {
+    pub buffer: Cooldown
 }
 
-impl Prompt {
-    pub fn new() -> Self {
-        Self {
-            appear: false,
-            text: (String::new(), String::new()),
-            has_chosen: false
-        }
+impl BufferedInput {
+    pub fn new(window: f32) -> Self {
+        Self { buffer: Cooldown { cooldown: window, cooldown_value: 0.0 } }
     }
-    pub fn prompt(&mut self, player: &Player) {
-        self.appear = true;
-        let (gun, sword) = (player.weapons[0].get_gun().unwrap(), player.weapons[1].get_sword().unwrap());
-        match gun.level {
-            0 => self.text.0 = format!("Get new weapon\nGun; Q"),
-            _ => self.text.0 = format!("Upgrade Gun\nto level {}; Q", gun.level + 1)
-        }
-        match sword.level {
-            0 => self.text.1 = format!("Get new weapon\nSword; E"),
-            _ => self.text.1 = format!("Upgrade Sword\nto level {}; E", sword.level + 1)
-        }
-        
+    pub fn press(&mut self) {
+        self.buffer.cooldown_value = self.buffer.cooldown;
     }
-    pub fn select(level: &mut Level, rl: &mut RaylibHandle, player: &mut Player) {
-        let input = rl.get_key_pressed();
-        match input {
-            Some(i) => { match i {
-                KeyboardKey::KEY_Q => { 
-                    let gun = player.weapons[0].get_gun_mut().unwrap();
-                    gun.add_level(); 
-                    level.prompt.has_chosen = true; 
-                    level.prompt.appear = false; 
-                    player.damage.hitpoint += 1;
-                    player.level += 1;
-                    level.enemy_cooldown.cooldown -= player.level as f32 / 3.5
-                }
-                KeyboardKey::KEY_E => { 
-                    let sword = player.weapons[1].get_sword_mut().unwrap();
-                    sword.add_level(); 
-                    level.prompt.has_chosen = true; 
-                    level.prompt.appear = false; 
-                    player.damage.hitpoint += 1;
-                    player.level += 1;
-                    level.enemy_cooldown.cooldown -= player.level as f32 / 3.5
-                }
-                _ => {}
-            }}
-            None => {}
-        }
+    pub fn tick(&mut self, dt: f32) {
+        self.buffer.cooldown_value = (self.buffer.cooldown_value - 10.0 * dt).max(0.0);
+    }
+    pub fn take(&mut self) -> bool {
+        let pressed = self.buffer.cooldown_value > 0.0;
+        self.buffer.cooldown_value = 0.0;
+        pressed
     }
 }
 
-pub fn round_to_nearest(x: f32, a: f32, b: f32) -> f32 {
-    let (min_val, max_val) = (a.min(b), a.max(b));
-    let diff_min = (x - min_val).abs();
-    let diff_max = (x - max_val).abs();
-    if x > min_val && x < max_val {
-        if diff_min > diff_max { b } else if diff_min < diff_max { a } else { a }
-    } else { x }
-}
\ No newline at end of file
+// Fraction of the remaining gap to close this frame so that half of it is gone every `half_life` seconds, whatever the frame rate
+pub fn damp_factor(half_life: f32, dt: f32) -> f32 {
+    if half_life <= 0.0 { return 1.0 }
+    1.0 - 0.5_f32.powf(dt / half_life)
+}
+
+pub fn damp(from: f32, to: f32, half_life: f32, dt: f32) -> f32 {
+    from + (to - from) * damp_factor(half_life, dt)
+}
+
+// Signed difference in degrees from one angle to another, always the short way round
+pub fn shortest_arc(from: f32, to: f32) -> f32 {
+    let delta = (to - from).rem_euclid(360.0);
+    if delta > 180.0 { delta - 360.0 } else { delta }
+}
+
+// Kept in 0..360 so repeated turns the same way don't pile up
+pub fn lerp_angle(from: f32, to: f32, t: f32) -> f32 {
+    (from + shortest_arc(from, to) * t).rem_euclid(360.0)
+}
+
+pub fn damp_angle(from: f32, to: f32, half_life: f32, dt: f32) -> f32 {
+    lerp_angle(from, to, damp_factor(half_life, dt))
+}
+
+// Float comparison for tests across the crate
+#[cfg(test)]
+pub(crate) fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffered_input_expires_and_is_taken_once() {
+        let mut input = BufferedInput::new(2.0);
+        assert!(!input.take());
+        input.press();
+        input.tick(0.1);
+        assert!(input.take());
+        assert!(!input.take());
+        // Held for 0.2 seconds and no longer
+        input.press();
+        input.tick(0.25);
+        assert!(!input.take());
+    }
+
+    #[test]
+    fn shortest_arc_goes_the_short_way() {
+        assert!(close(shortest_arc(170.0, -170.0), 20.0));
+        assert!(close(shortest_arc(-170.0, 170.0), -20.0));
+        assert!(close(shortest_arc(10.0, 350.0), -20.0));
+        assert!(close(shortest_arc(0.0, 720.0), 0.0));
+    }
+
+    #[test]
+    fn damp_angle_crosses_180_not_0() {
+        let mut angle = 179.0;
+        for _ in 0..60 {
+            angle = damp_angle(angle, -179.0, 0.05, 1.0 / 60.0);
+            // -179 is 181 in 0..360, so the way there stays right around 180
+            assert!((179.0..=181.0).contains(&angle), "passed through {angle}");
+        }
+        assert!(shortest_arc(angle, -179.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn damp_factor_halves_per_half_life() {
+        assert_eq!(damp_factor(0.1, 0.0), 0.0);
+        assert!(close(damp_factor(0.1, 0.1), 0.5));
+        assert!(close(damp_factor(0.1, 0.2), 0.75));
+        assert_eq!(damp_factor(0.0, 0.1), 1.0);
+        assert!(close(damp(10.0, 20.0, 0.1, 0.1), 15.0));
+    }
+}
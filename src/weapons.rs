@@ -1,6 +1,11 @@
 use crate::basic::{RectanglePro, BallEnt};
-use crate::entities::{Player};
-use crate::utils::Cooldown;
+use crate::entities::{Player, Level};
+use crate::utils::{Cooldown, damp_angle, damp_factor};
+use crate::stats::{Modifier, Stat, Stats};
+use crate::status::StatusKind;
+use crate::events::{GameEvent, WeaponKind};
+use crate::anim::{AnimBook, Tween};
+use crate::items::shot_handler;
 use raylib::{color::Color, prelude::*};
 use std::{ops::Add};
 use crate::{WIDTH, HEIGHT};
@@ -37,13 +42,134 @@ impl Weapon {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Evolution {
+    RicochetStorm,
+    Whirlwind
+}
+
+impl Evolution {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::RicochetStorm => "Ricochet Storm",
+            Self::Whirlwind => "Whirlwind"
+        }
+    }
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::RicochetStorm => "+2 projectiles,\n+2 bounces",
+            Self::Whirlwind => "Sword swings a\nfull circle,\n+50% damage"
+        }
+    }
+}
+
+// A maxed weapon plus a passive that has pushed `passive` above its base evolves
+pub struct Recipe {
+    pub weapon: WeaponKind,
+    pub passive: Stat,
+    pub evolution: Evolution
+}
+
+pub const RECIPES: [Recipe; 2] = [
+    Recipe { weapon: WeaponKind::Gun, passive: Stat::Bounces, evolution: Evolution::RicochetStorm },
+    Recipe { weapon: WeaponKind::Sword, passive: Stat::SwordReach, evolution: Evolution::Whirlwind }
+];
+
+// Evolutions whose recipe is met and that haven't been taken yet
+pub fn ready_evolutions(player: &Player) -> Vec<Evolution> {
+    let (gun, sword) = (player.weapons[0].get_gun().unwrap(), player.weapons[1].get_sword().unwrap());
+    RECIPES.iter()
+        .filter(|recipe| match recipe.weapon {
+            WeaponKind::Gun => gun.is_maxed() && gun.evolution.is_none(),
+            WeaponKind::Sword => sword.is_maxed() && sword.evolution.is_none()
+        })
+        .filter(|recipe| player.stats.get(recipe.passive) > recipe.passive.base())
+        .map(|recipe| recipe.evolution)
+        .collect()
+}
+
+pub fn evolve(player: &mut Player, evolution: Evolution) {
+    match evolution {
+        Evolution::RicochetStorm => {
+            player.weapons[0].get_gun_mut().unwrap().evolution = Some(evolution);
+            player.stats.push(Modifier::Add(Stat::Projectiles, 2.0));
+            player.stats.push(Modifier::Add(Stat::Bounces, 2.0));
+        }
+        Evolution::Whirlwind => {
+            player.weapons[1].get_sword_mut().unwrap().evolution = Some(evolution);
+            player.stats.push(Modifier::Mul(Stat::SwordDamage, 1.5));
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SwingKind {
+    Slash,
+    Backslash,
+    Thrust,
+    // Only from a charged hold, never part of the combo
+    Spin
+}
+
+impl SwingKind {
+    // Presses inside the combo window walk through these in order
+    pub const COMBO: [Self; 3] = [Self::Slash, Self::Backslash, Self::Thrust];
+
+    // Where `swing_progress` ends up, starting from its current angle
+    pub fn target(&self, from: f32) -> f32 {
+        match self {
+            Self::Slash => 0.0,
+            Self::Backslash => 150.0,
+            Self::Thrust => 37.0,
+            Self::Spin => from - 360.0
+        }
+    }
+    // Its curve among the sword's
+    pub fn motion(&self) -> &'static str {
+        match self {
+            Self::Slash => "slash",
+            Self::Backslash => "backslash",
+            Self::Thrust => "thrust",
+            Self::Spin => "spin"
+        }
+    }
+    pub fn reach(&self) -> f32 {
+        match self {
+            Self::Slash | Self::Backslash => 1.0,
+            Self::Thrust => 1.6,
+            Self::Spin => 1.3
+        }
+    }
+    pub fn damage(&self) -> f32 {
+        match self {
+            Self::Slash => 1.0,
+            Self::Backslash => 1.2,
+            Self::Thrust => 1.5,
+            Self::Spin => 2.5
+        }
+    }
+}
+
 pub struct Sword {
     pub fields: RectanglePro,
     pub offset: Vector2,
-    pub level: u128,
-    pub damage: f32,
+    pub level: u8,
+    pub evolution: Option<Evolution>,
+    pub effects: Vec<StatusKind>,
     pub is_swinging: bool,
-    pub swing_progress: f32
+    pub swing_progress: f32,
+    // Smoothed angle the blade points at before the swing is added
+    pub facing: f32,
+    pub swing: SwingKind,
+    // The swing in progress, or the return to rest once it is over
+    pub motion: Tween,
+    pub combo: usize,
+    // Open for a moment after each swing, the next press continues the chain
+    pub combo_window: Cooldown,
+    // Seconds the swing button has been held
+    pub charge: f32,
+    // Start of a swing, when it sends enemy projectiles back
+    pub parry: Cooldown
 }
 
 impl Sword {
@@ -57,26 +183,226 @@ impl Sword {
                 color: Color::SILVER 
             },
             level: 0,
-            damage: 0.0,
+            evolution: None,
+            effects: Vec::new(),
             offset: Vector2 { x: HEIGHT/20.0, y: 0.0 },
             is_swinging: false,
-            swing_progress: 75.0
+            swing_progress: Self::REST,
+            facing: 0.0,
+            swing: SwingKind::Slash,
+            motion: Tween::still(Self::REST),
+            combo: 0,
+            combo_window: Cooldown {
+                cooldown: 6.0,
+                cooldown_value: 0.0
+            },
+            charge: 0.0,
+            parry: Cooldown {
+                cooldown: 1.5,
+                cooldown_value: 0.0
+            }
         }
     }
-    pub fn swing(&mut self) {
+    pub const MAX_LEVEL: u8 = 8;
+    // Its curves in the animation file, one per swing plus the return to rest
+    pub const CURVE_OWNER: &str = "sword";
+    pub const CURVES: [&str; 5] = ["slash", "backslash", "thrust", "spin", "return"];
+    pub const CHARGE_TIME: f32 = 0.6;
+    // Angle the blade hangs at between swings
+    pub const REST: f32 = 75.0;
+    // Where the blade's base sits around the player, and how the blade is angled on top of that
+    const PIVOT: f32 = -PI as f32 / 2.0;
+    const GRIP: f32 = -PI as f32 / 4.0;
+
+    // A whirlwind winds up a full turn further before coming down
+    pub fn swing(&mut self, kind: SwingKind, animations: &AnimBook) {
         self.is_swinging = true;
+        self.swing = kind;
+        if self.evolution == Some(Evolution::Whirlwind) { self.swing_progress += 360.0 }
+        self.motion = Tween::new(animations.curve(Self::CURVE_OWNER, kind.motion()), self.swing_progress, kind.target(self.swing_progress));
+        self.parry.cooldown_value = self.parry.cooldown;
+    }
+    pub fn next_combo(&mut self) -> SwingKind {
+        self.combo = if self.combo_window.cooldown_value > 0.0 { (self.combo + 1) % SwingKind::COMBO.len() } else { 0 };
+        SwingKind::COMBO[self.combo]
+    }
+    // Held long enough that letting go spins
+    pub fn is_charged(&self) -> bool {
+        self.charge >= Self::CHARGE_TIME
+    }
+    pub fn can_parry(&self) -> bool {
+        self.is_swinging && self.parry.cooldown_value > 0.0
     }
-    pub fn add_level(&mut self) {
+    pub fn is_maxed(&self) -> bool {
+        self.level >= Self::MAX_LEVEL
+    }
+    pub fn add_level(&mut self, stats: &mut Stats) {
+        if self.is_maxed() { return }
         self.level += 1;
-        self.damage += 1.0 / self.level as f32;
+        stats.push(Modifier::Add(Stat::SwordDamage, 1.0 / self.level as f32));
+    }
+}
+
+// The first branch upgrade commits the gun to that path for the rest of the run
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GunBranch {
+    // More projectiles in a wider fan
+    Scatter,
+    // Faster bullets that pass through enemies
+    Lance
+}
+
+// Everything a shot needs, resolved from stats once per frame
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct GunProfile {
+    pub projectiles: u8,
+    pub spread: f32,
+    pub pierce: u8,
+    pub bounces: u8,
+    pub speed: f32,
+    pub size: f32
+}
+
+impl GunProfile {
+    pub fn from_stats(stats: &Stats) -> Self {
+        Self {
+            projectiles: stats.get_u8(Stat::Projectiles),
+            spread: stats.get(Stat::Spread),
+            pierce: stats.get_u8(Stat::Pierce),
+            bounces: stats.get_u8(Stat::Bounces),
+            speed: stats.get(Stat::BulletSpeed),
+            size: stats.get(Stat::BulletSize)
+        }
+    }
+    // Angle offsets in degrees, centred on the barrel
+    pub fn fan(&self) -> impl Iterator<Item = f32> {
+        let (count, spread) = (self.projectiles.max(1), self.spread);
+        (0..count).map(move |index| (index as f32 - (count - 1) as f32 / 2.0) * spread)
+    }
+}
+
+pub struct Magazine {
+    pub size: u8,
+    pub rounds: u8,
+    pub reload: Cooldown,
+    pub reloading: bool,
+    // Fraction of the reload bar where a press finishes it on the spot
+    pub window: (f32, f32),
+    // One active reload attempt per reload, a miss starts the bar over
+    pub attempted: bool
+}
+
+impl Magazine {
+    pub fn new(size: u8, reload_seconds: f32) -> Self {
+        Self {
+            size,
+            rounds: size,
+            reload: Cooldown {
+                cooldown: reload_seconds * 10.0,
+                cooldown_value: 0.0
+            },
+            reloading: false,
+            window: (0.45, 0.65),
+            attempted: false
+        }
+    }
+    // 0 when a reload starts, 1 when it is done
+    pub fn progress(&self) -> f32 {
+        1.0 - self.reload.cooldown_value / self.reload.cooldown
+    }
+    fn start_reload(&mut self) {
+        self.reloading = true;
+        self.attempted = false;
+        self.reload.cooldown_value = self.reload.cooldown;
+    }
+    fn finish_reload(&mut self) {
+        self.reloading = false;
+        self.rounds = self.size;
+        self.reload.cooldown_value = 0.0;
+    }
+}
+
+pub struct Overheat {
+    // 0 is cold, 1 locks the gun until it has fully cooled down
+    pub heat: f32,
+    pub per_shot: f32,
+    pub cooling: f32,
+    pub overheated: bool
+}
+
+impl Overheat {
+    pub fn new(per_shot: f32, cooling: f32) -> Self {
+        Self { heat: 0.0, per_shot, cooling, overheated: false }
+    }
+}
+
+// How the gun turns a held fire key into shots, on top of the time between shots
+pub enum FireMode {
+    Auto,
+    Magazine(Magazine),
+    Overheat(Overheat)
+}
+
+impl FireMode {
+    pub fn can_fire(&self) -> bool {
+        match self {
+            Self::Auto => true,
+            Self::Magazine(magazine) => !magazine.reloading && magazine.rounds > 0,
+            Self::Overheat(overheat) => !overheat.overheated
+        }
+    }
+    pub fn fired(&mut self) {
+        match self {
+            Self::Auto => {}
+            Self::Magazine(magazine) => {
+                magazine.rounds -= 1;
+                if magazine.rounds == 0 { magazine.start_reload() }
+            }
+            Self::Overheat(overheat) => {
+                overheat.heat += overheat.per_shot;
+                if overheat.heat >= 1.0 {
+                    overheat.heat = 1.0;
+                    overheat.overheated = true;
+                }
+            }
+        }
+    }
+    // Starts a reload early, or during one tries to hit the active reload window
+    pub fn reload_pressed(&mut self) {
+        let Self::Magazine(magazine) = self else { return };
+        if !magazine.reloading {
+            if magazine.rounds < magazine.size { magazine.start_reload() }
+            return
+        }
+        if magazine.attempted { return }
+        magazine.attempted = true;
+        let progress = magazine.progress();
+        if progress >= magazine.window.0 && progress <= magazine.window.1 { magazine.finish_reload() } else { magazine.reload.cooldown_value = magazine.reload.cooldown }
+    }
+    pub fn tick(&mut self, dt: f32) {
+        match self {
+            Self::Auto => {}
+            Self::Magazine(magazine) => if magazine.reloading {
+                magazine.reload.cooldown_value -= 10.0 * dt;
+                if magazine.reload.cooldown_value <= 0.0 { magazine.finish_reload() }
+            },
+            Self::Overheat(overheat) => {
+                overheat.heat = (overheat.heat - overheat.cooling * dt).max(0.0);
+                if overheat.heat <= 0.0 { overheat.overheated = false }
+            }
+        }
     }
 }
 
 pub struct Gun {
     pub fields: RectanglePro,
     pub offset: Vector2,
-    pub level: u128,
+    pub level: u8,
+    pub evolution: Option<Evolution>,
+    pub branch: Option<GunBranch>,
+    pub mode: FireMode,
     pub reload: Cooldown,
+    pub effects: Vec<StatusKind>,
     pub bullets: Vec<Bullet>,
 }
 
@@ -91,16 +417,33 @@ impl Gun {
             },
             offset: Vector2 { x: HEIGHT/36.0, y: HEIGHT/36.0 },
             level: 1,
+            evolution: None,
+            branch: None,
+            mode: FireMode::Auto,
             reload: Cooldown { 
                 cooldown: 10.0, 
                 cooldown_value: 0.0 
             },
+            effects: Vec::new(),
             bullets: Vec::new()
         }
     }
-    pub fn add_level(&mut self) {
+    pub const MAX_LEVEL: u8 = 8;
+    // Its curve in the animation file, the kick after each shot
+    pub const CURVE_OWNER: &str = "gun";
+    pub const CURVES: [&str; 1] = ["recoil"];
+
+    pub fn is_maxed(&self) -> bool {
+        self.level >= Self::MAX_LEVEL
+    }
+    pub fn add_level(&mut self, stats: &mut Stats) {
+        if self.is_maxed() { return }
         self.level += 1;
-        self.reload.cooldown -= 5.0 / self.level as f32;
+        stats.push(Modifier::Add(Stat::ReloadTime, -5.0 / self.level as f32));
+    }
+    // How far back the gun sits, sampled over the time between shots
+    pub fn recoil(&self, animations: &AnimBook) -> f32 {
+        animations.curve(Self::CURVE_OWNER, "recoil").sample(1.0 - self.reload.cooldown_value / self.reload.cooldown)
     }
 }
 
@@ -108,31 +451,39 @@ impl Gun {
 pub struct Bullet {
     pub fields: BallEnt,
     pub bounces: u8,
+    pub pierce: u8,
     pub hit_enemy: bool
 }
 
 impl Bullet {
-    pub fn new(gun: &Gun) -> Self {
+    pub fn new(gun: &Gun, profile: &GunProfile, angle: f32) -> Self {
+        let rotation = gun.fields.rotation.add(-90.0 + angle).to_radians();
         Self {
             fields: BallEnt {
                 position: Vector2 { x: gun.fields.rect.x, y: gun.fields.rect.y },
-                direction: Vector2::from(Vector2 {x: gun.fields.rotation.add(-90.0).to_radians().cos(), y: gun.fields.rotation.add(-90.0).to_radians().sin()}).normalized(),
-                speed: HEIGHT/(720.0/500.0),
-                radius: HEIGHT/144.0,
+                direction: Vector2::from(Vector2 {x: rotation.cos(), y: rotation.sin()}).normalized(),
+                speed: profile.speed,
+                radius: profile.size,
                 color: Color::GOLD
             },
             bounces: 0,
+            pierce: profile.pierce,
             hit_enemy: false
         }
     }
 }
 
-pub fn weapon_handler(rl: &RaylibHandle, player: &mut Player) {
+// Seconds for the aim and the weapons to close half the distance to where they are headed
+const AIM_HALF_LIFE: f32 = 0.027;
+const WEAPON_HALF_LIFE: f32 = 0.017;
+
+pub fn weapon_handler(rl: &RaylibHandle, player: &mut Player, level: &mut Level) {
+    let reload_multiplier = player.reload_multiplier();
     let direction = &mut player.fields.direction;
     let direction_angle = direction.y.atan2(direction.x);
-    let rotation_smoothing = 0.35;
-
-    const MAX_BOUNCES: u8 = 1;
+    let rotation_smoothing = damp_factor(AIM_HALF_LIFE, rl.get_frame_time());
+    let stats = &player.stats;
+    let profile = GunProfile::from_stats(stats);
     
     if rl.is_key_down(KeyboardKey::KEY_UP) { 
         if rl.is_key_down(KeyboardKey::KEY_LEFT) || rl.is_key_down(KeyboardKey::KEY_DOWN) || rl.is_key_down(KeyboardKey::KEY_RIGHT) { 
@@ -163,34 +514,69 @@ pub fn weapon_handler(rl: &RaylibHandle, player: &mut Player) {
                 x: lerp(direction.x, 1.0, rotation_smoothing), 
                 y: lerp(direction.y, 0.0, rotation_smoothing) }}}
 
+    // Aim modes pull the arrow-key aim toward a target, auto aim also pulls the trigger
+    if let Some((aim, half_life)) = player.aim.update(*direction, player.fields.position, &level.enemies, &level.nav) {
+        let pull = damp_factor(half_life, rl.get_frame_time());
+        *direction = Vector2 { x: lerp(direction.x, aim.x, pull), y: lerp(direction.y, aim.y, pull) };
+    }
+    let auto_fire = player.aim.auto_fire();
+
     let sword = player.weapons[1].get_sword_mut().unwrap();
 
     if rl.is_key_pressed(KeyboardKey::KEY_C) {
         if player.equipped + 1 > 1 { player.equipped = 0 } else { player.equipped += 1 }
         if player.equipped == 1 && sword.level == 0 { player.equipped = 0 }
     }
-    if rl.is_key_pressed(KeyboardKey::KEY_SPACE) { sword.swing() }
+    // Swings pressed mid-swing or during knockback are held briefly instead of dropped
+    if rl.is_key_pressed(KeyboardKey::KEY_SPACE) { player.swing_input.press() }
+    player.swing_input.tick(rl.get_frame_time());
+    // Only a drawn, unlocked sword swings
+    let armed = player.equipped == 1 && sword.level > 0;
+    let ready = armed && !sword.is_swinging && player.damage.damage_cooldown.cooldown_value <= 20.0;
+    if ready && player.swing_input.take() {
+        let kind = sword.next_combo();
+        sword.swing(kind, &level.animations);
+        level.events.emit(GameEvent::WeaponFired { weapon: WeaponKind::Sword, position: player.fields.position });
+    }
+    // Keep holding past the opening swing and let go to spin
+    if rl.is_key_down(KeyboardKey::KEY_SPACE) && armed { sword.charge += rl.get_frame_time() } else {
+        if ready && sword.is_charged() {
+            sword.swing(SwingKind::Spin, &level.animations);
+            level.events.emit(GameEvent::WeaponFired { weapon: WeaponKind::Sword, position: player.fields.position });
+        }
+        sword.charge = 0.0;
+    }
+    sword.parry.cooldown_value = (sword.parry.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
+    sword.combo_window.cooldown_value = (sword.combo_window.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
     
     let sword_offset = Vector2 { 
-        x: sword.offset.x * direction_angle.add((-PI/2.0) as f32 + sword.swing_progress.to_radians()).cos() - sword.offset.y * direction_angle.add((-PI/2.0) as f32 + sword.swing_progress.to_radians()).sin(), 
-        y: sword.offset.x * direction_angle.add((-PI/2.0) as f32 + sword.swing_progress.to_radians()).sin() + sword.offset.y * direction_angle.add((-PI/2.0) as f32 + sword.swing_progress.to_radians()).cos()
+        x: sword.offset.x * direction_angle.add(Sword::PIVOT + sword.swing_progress.to_radians()).cos() - sword.offset.y * direction_angle.add(Sword::PIVOT + sword.swing_progress.to_radians()).sin(), 
+        y: sword.offset.x * direction_angle.add(Sword::PIVOT + sword.swing_progress.to_radians()).sin() + sword.offset.y * direction_angle.add(Sword::PIVOT + sword.swing_progress.to_radians()).cos()
     };
     
-    if sword.swing_progress <= 0.0 { sword.is_swinging = false }
-    if sword.is_swinging { sword.swing_progress = lerp(sword.swing_progress, -1.0, 0.25) } 
-    else if sword.swing_progress != 75.0 { sword.swing_progress = lerp(sword.swing_progress, 75.0, 0.5) }
+    // Swings and the return to rest are both curves from the animation file
+    sword.motion.tick(rl.get_frame_time());
+    sword.swing_progress = sword.motion.value();
+    if sword.is_swinging && sword.motion.done() {
+        sword.is_swinging = false;
+        sword.combo_window.cooldown_value = sword.combo_window.cooldown;
+        sword.motion = Tween::new(level.animations.curve(Sword::CURVE_OWNER, "return"), sword.swing_progress, Sword::REST);
+    }
 
     sword.fields.rect = ffi::Rectangle { 
         x: player.fields.position.x + sword_offset.x,
         y: player.fields.position.y + sword_offset.y,
-        width: sword.fields.rect.width,
+        width: stats.get(Stat::SwordReach) * if sword.is_swinging { sword.swing.reach() } else { 1.0 },
         height: sword.fields.rect.height
     };
-    sword.fields.rotation = lerp(sword.fields.rotation, direction_angle.add((-PI/4.0) as f32).to_degrees(), 0.5).add(sword.swing_progress);
+    // The blade turns twice as far as it travels around the player
+    sword.facing = damp_angle(sword.facing, direction_angle.add(Sword::GRIP).to_degrees(), WEAPON_HALF_LIFE, rl.get_frame_time());
+    sword.fields.rotation = sword.facing + 2.0 * sword.swing_progress;
     
     let gun = player.weapons[0].get_gun_mut().unwrap();
+    gun.reload.cooldown = stats.get(Stat::ReloadTime);
 
-    let recoil_x = gun.offset.x - (5.0 * gun.reload.cooldown_value / gun.reload.cooldown);
+    let recoil_x = gun.offset.x - gun.recoil(&level.animations);
 
     let gun_offset = Vector2 {
         x: recoil_x * direction_angle.cos() - gun.offset.y * direction_angle.sin(), 
@@ -199,21 +585,160 @@ pub fn weapon_handler(rl: &RaylibHandle, player: &mut Player) {
 
     gun.fields.rect.x = player.fields.position.x + gun_offset.x;
     gun.fields.rect.y = player.fields.position.y + gun_offset.y;
-    gun.fields.rotation = lerp(gun.fields.rotation, direction_angle.to_degrees().add(90.0), 0.5);
+    gun.fields.rotation = damp_angle(gun.fields.rotation, direction_angle.to_degrees().add(90.0), WEAPON_HALF_LIFE, rl.get_frame_time());
 
-    if rl.is_key_down(KeyboardKey::KEY_LEFT) || rl.is_key_down(KeyboardKey::KEY_RIGHT) || rl.is_key_down(KeyboardKey::KEY_DOWN) || rl.is_key_down(KeyboardKey::KEY_UP) {
-        if gun.reload.cooldown_value <= 0.0 && player.equipped == 0 {
-            let bullet = Bullet::new(gun);
-            gun.bullets.push(bullet);
-            if player.damage.hitpoint != 0 { gun.reload.cooldown_value = gun.reload.cooldown } else { gun.reload.cooldown_value = gun.reload.cooldown/5.0 }
+    if rl.is_key_down(KeyboardKey::KEY_LEFT) || rl.is_key_down(KeyboardKey::KEY_RIGHT) || rl.is_key_down(KeyboardKey::KEY_DOWN) || rl.is_key_down(KeyboardKey::KEY_UP) || auto_fire {
+        if gun.reload.cooldown_value <= 0.0 && player.equipped == 0 && gun.mode.can_fire() {
+            let fired: Vec<Bullet> = profile.fan().map(|angle| Bullet::new(gun, &profile, angle)).collect();
+            gun.reload.cooldown_value = gun.reload.cooldown * reload_multiplier;
+            gun.mode.fired();
+            // Shot items act on each bullet on its way out, so pierce lands on the one that earned it
+            for mut bullet in fired {
+                level.events.emit(GameEvent::WeaponFired { weapon: WeaponKind::Gun, position: bullet.fields.position });
+                shot_handler(level, player, &mut bullet);
+                player.weapons[0].get_gun_mut().unwrap().bullets.push(bullet);
+            }
         }
     }
+    let gun = player.weapons[0].get_gun_mut().unwrap();
+    if rl.is_key_pressed(KeyboardKey::KEY_R) || (rl.is_gamepad_available(0) && rl.is_gamepad_button_pressed(0, GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_LEFT)) { gun.mode.reload_pressed() }
+    gun.mode.tick(rl.get_frame_time());
     gun.reload.cooldown_value = (gun.reload.cooldown_value - 10.0 * rl.get_frame_time()).max(0.0);
     gun.bullets.retain_mut(|bullet| {
         if bullet.fields.position.x <= 0.0 || bullet.fields.position.x >= WIDTH { bullet.fields.direction.x = -bullet.fields.direction.x; bullet.bounces += 1 }
         if bullet.fields.position.y <= 0.0 || bullet.fields.position.y >= HEIGHT { bullet.fields.direction.y = -bullet.fields.direction.y; bullet.bounces += 1 }
         let velocity = Vector2::scale_by(&bullet.fields.direction, bullet.fields.speed * rl.get_frame_time());
         bullet.fields.position = Vector2::add(bullet.fields.position, velocity);
-        bullet.bounces <= MAX_BOUNCES && bullet.hit_enemy == false
+        let hit_obstacle = level.obstacles.iter().any(|obstacle| obstacle.check_collision_circle_rec(bullet.fields.position, bullet.fields.radius));
+        bullet.bounces <= profile.bounces && bullet.hit_enemy == false && !hit_obstacle
     });
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn magazine(mode: &FireMode) -> &Magazine {
+        let FireMode::Magazine(magazine) = mode else { panic!("not a magazine") };
+        magazine
+    }
+
+    #[test]
+    fn evolutions_need_a_maxed_weapon_and_its_passive() {
+        let mut player = Player::new();
+        player.stats.push(Modifier::Add(Stat::Bounces, 1.0));
+        player.weapons[0].get_gun_mut().unwrap().level = Gun::MAX_LEVEL - 1;
+        assert!(ready_evolutions(&player).is_empty());
+        player.weapons[0].get_gun_mut().unwrap().level = Gun::MAX_LEVEL;
+        assert_eq!(ready_evolutions(&player), vec![Evolution::RicochetStorm]);
+        // A maxed sword without extra reach has no partner yet
+        player.weapons[1].get_sword_mut().unwrap().level = Sword::MAX_LEVEL;
+        assert_eq!(ready_evolutions(&player), vec![Evolution::RicochetStorm]);
+        evolve(&mut player, Evolution::RicochetStorm);
+        assert!(ready_evolutions(&player).is_empty());
+        player.stats.push(Modifier::Add(Stat::SwordReach, 1.0));
+        assert_eq!(ready_evolutions(&player), vec![Evolution::Whirlwind]);
+    }
+
+    #[test]
+    fn fan_spreads_evenly_around_the_barrel() {
+        let mut profile = GunProfile::from_stats(&Stats::new());
+        profile.spread = 10.0;
+        profile.projectiles = 1;
+        assert_eq!(profile.fan().collect::<Vec<_>>(), vec![0.0]);
+        profile.projectiles = 0;
+        assert_eq!(profile.fan().collect::<Vec<_>>(), vec![0.0]);
+        profile.projectiles = 3;
+        assert_eq!(profile.fan().collect::<Vec<_>>(), vec![-10.0, 0.0, 10.0]);
+        profile.projectiles = 4;
+        assert_eq!(profile.fan().collect::<Vec<_>>(), vec![-15.0, -5.0, 5.0, 15.0]);
+    }
+
+    #[test]
+    fn combo_chains_while_the_window_is_open() {
+        let mut sword = Sword::new();
+        assert_eq!(sword.next_combo(), SwingKind::Slash);
+        sword.combo_window.cooldown_value = sword.combo_window.cooldown;
+        assert_eq!(sword.next_combo(), SwingKind::Backslash);
+        assert_eq!(sword.next_combo(), SwingKind::Thrust);
+        assert_eq!(sword.next_combo(), SwingKind::Slash);
+        assert_eq!(sword.next_combo(), SwingKind::Backslash);
+        // Waiting too long starts the chain over
+        sword.combo_window.cooldown_value = 0.0;
+        assert_eq!(sword.next_combo(), SwingKind::Slash);
+    }
+
+    #[test]
+    fn holding_past_the_charge_time_spins() {
+        let mut sword = Sword::new();
+        sword.charge = Sword::CHARGE_TIME - 0.01;
+        assert!(!sword.is_charged());
+        sword.charge = Sword::CHARGE_TIME;
+        assert!(sword.is_charged());
+    }
+
+    #[test]
+    fn parries_only_at_the_start_of_a_swing() {
+        let animations = AnimBook::parse("curve sword.slash 0=0 0.2=1").unwrap();
+        let mut sword = Sword::new();
+        assert!(!sword.can_parry());
+        sword.swing(SwingKind::Slash, &animations);
+        assert!(sword.can_parry());
+        sword.parry.cooldown_value = 0.0;
+        assert!(!sword.can_parry());
+        sword.parry.cooldown_value = sword.parry.cooldown;
+        sword.is_swinging = false;
+        assert!(!sword.can_parry());
+    }
+
+    #[test]
+    fn active_reload_finishes_inside_the_window_and_restarts_on_a_miss() {
+        let mut mode = FireMode::Magazine(Magazine::new(6, 1.0));
+        mode.fired();
+        mode.reload_pressed();
+        mode.tick(0.55);
+        mode.reload_pressed();
+        assert!(!magazine(&mode).reloading);
+        assert_eq!(magazine(&mode).rounds, 6);
+
+        mode.fired();
+        mode.reload_pressed();
+        mode.tick(0.2);
+        mode.reload_pressed();
+        assert!(magazine(&mode).reloading);
+        assert_eq!(magazine(&mode).progress(), 0.0);
+        // Only one try per reload, so pressing again inside the window does nothing
+        mode.tick(0.55);
+        mode.reload_pressed();
+        assert!(magazine(&mode).reloading);
+        mode.tick(0.45);
+        assert!(!magazine(&mode).reloading);
+    }
+
+    #[test]
+    fn an_empty_magazine_reloads() {
+        let mut mode = FireMode::Magazine(Magazine::new(2, 1.0));
+        mode.fired();
+        assert!(mode.can_fire());
+        mode.fired();
+        assert!(magazine(&mode).reloading);
+        assert!(!mode.can_fire());
+        mode.tick(1.0);
+        assert!(mode.can_fire());
+        assert_eq!(magazine(&mode).rounds, 2);
+    }
+
+    #[test]
+    fn overheating_locks_the_gun_until_it_is_cold() {
+        let mut mode = FireMode::Overheat(Overheat::new(0.5, 1.0));
+        mode.fired();
+        assert!(mode.can_fire());
+        mode.fired();
+        assert!(!mode.can_fire());
+        mode.tick(0.25);
+        assert!(!mode.can_fire());
+        mode.tick(0.25);
+        assert!(!mode.can_fire());
+        mode.tick(0.5);
+        assert!(mode.can_fire());
+    }
+}